@@ -0,0 +1,68 @@
+//! A scripted stand-in for a language server, used by the LSP client's
+//! tests: `fake_lsp <script.json> <log>`.
+//!
+//! The script is a JSON object. `responses` maps a request method to the
+//! result to answer it with (requests not in it get `null`);
+//! `capabilities` is returned from `initialize`; `on_open` lists
+//! notifications to send after each `textDocument/didOpen`. Every message
+//! received is appended to the log, one per line.
+
+use serde_json::{json, Value};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok()?;
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body).ok();
+    writer.flush().ok();
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let script: Value = serde_json::from_str(&fs::read_to_string(&args[1]).expect("script")).expect("script json");
+    let mut log = OpenOptions::new().create(true).append(true).open(&args[2]).expect("log");
+
+    let mut reader = BufReader::new(io::stdin());
+    let mut stdout = io::stdout();
+    while let Some(message) = read_message(&mut reader) {
+        writeln!(log, "{}", message).ok();
+        let method = message.get("method").and_then(Value::as_str).unwrap_or("");
+        if let Some(id) = message.get("id") {
+            let result = match method {
+                "initialize" => json!({ "capabilities": script.get("capabilities").cloned().unwrap_or(json!({})) }),
+                _ => script.pointer(&format!("/responses/{}", method.replace('/', "~1"))).cloned().unwrap_or(Value::Null),
+            };
+            write_message(&mut stdout, &json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+        }
+        match method {
+            "textDocument/didOpen" => {
+                for notification in script.get("on_open").and_then(Value::as_array).into_iter().flatten() {
+                    write_message(&mut stdout, &json!({ "jsonrpc": "2.0", "method": notification["method"], "params": notification["params"] }));
+                }
+            }
+            "exit" => break,
+            _ => {}
+        }
+    }
+}
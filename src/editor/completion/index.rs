@@ -0,0 +1,765 @@
+//! A rough index of the C++ declarations in a file, for completing
+//! members after `.`, `->` and `::`: struct and class members, namespace
+//! contents, `typedef`/`using` aliases and the declared types of
+//! variables. It reads tokens, not a full grammar, so unusual code is
+//! simply not indexed.
+
+use crate::editor::completion::stl;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MemberKind {
+    Field,
+    Method,
+    Type,
+}
+
+#[derive(Clone)]
+pub struct Member {
+    pub name: String,
+    pub kind: MemberKind,
+    /// The member's type, or a method's return type.
+    pub ty: String,
+    /// The declaration as written, for the preview pane.
+    pub signature: String,
+}
+
+#[derive(Default)]
+pub struct Index {
+    /// Members of each struct or class.
+    pub records: HashMap<String, Vec<Member>>,
+    /// Declarations inside each namespace.
+    pub namespaces: HashMap<String, Vec<Member>>,
+    /// Type aliases, by alias.
+    pub aliases: HashMap<String, String>,
+    /// Signatures of functions outside classes.
+    pub functions: HashMap<String, String>,
+}
+
+#[derive(Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    start: usize,
+}
+
+/// Blanks out comments, string and character literals and preprocessor
+/// lines, keeping every other byte where it was.
+fn strip(source: &str) -> String {
+    let bytes = source.as_bytes();
+    let mut out = bytes.to_vec();
+    let blank = |out: &mut Vec<u8>, from: usize, to: usize| {
+        for b in &mut out[from..to] {
+            if *b != b'\n' {
+                *b = b' ';
+            }
+        }
+    };
+    let mut i = 0;
+    let mut line_start = true;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let end = if c == b'#' && line_start {
+            bytes[i..].iter().position(|&b| b == b'\n').map_or(bytes.len(), |p| i + p)
+        } else if bytes[i..].starts_with(b"//") {
+            bytes[i..].iter().position(|&b| b == b'\n').map_or(bytes.len(), |p| i + p)
+        } else if bytes[i..].starts_with(b"/*") {
+            source[i + 2..].find("*/").map_or(bytes.len(), |p| i + 2 + p + 2)
+        } else if c == b'"' || c == b'\'' {
+            let mut j = i + 1;
+            while j < bytes.len() && bytes[j] != c && bytes[j] != b'\n' {
+                j += if bytes[j] == b'\\' { 2 } else { 1 };
+            }
+            (j + 1).min(bytes.len())
+        } else {
+            if c == b'\n' {
+                line_start = true;
+            } else if !c.is_ascii_whitespace() {
+                line_start = false;
+            }
+            i += 1;
+            continue;
+        };
+        blank(&mut out, i, end);
+        i = end;
+    }
+    // Only ASCII bytes outside literals and comments were kept as they are.
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn is_ident(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_alphabetic() || c == '_')
+}
+
+fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c.is_ascii_whitespace() || !c.is_ascii() {
+            i += 1;
+            continue;
+        }
+        let len = if c.is_ascii_alphanumeric() || c == b'_' {
+            bytes[i..].iter().position(|b| !(b.is_ascii_alphanumeric() || *b == b'_')).unwrap_or(bytes.len() - i)
+        } else if bytes[i..].starts_with(b"::") || bytes[i..].starts_with(b"->") {
+            2
+        } else {
+            1
+        };
+        tokens.push(Token { text: &text[i..i + len], start: i });
+        i += len;
+    }
+    tokens
+}
+
+/// Tokens as a type name: `unsigned long long`, `vector<pair<int,int>>`.
+fn join(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 && is_ident(token.text) && is_ident(tokens[i - 1].text) {
+            out.push(' ');
+        }
+        out.push_str(token.text);
+    }
+    out
+}
+
+// Words that may start a declaration but are not part of its type.
+const SPECIFIERS: &[&str] =
+    &["static", "inline", "virtual", "constexpr", "mutable", "explicit", "extern", "const", "volatile", "typename"];
+
+/// Index of the token closing the bracket opened at `open`, counting only
+/// `open_text`/`close_text` pairs.
+fn matching(tokens: &[Token], open: usize, open_text: &str, close_text: &str) -> usize {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        if token.text == open_text {
+            depth += 1;
+        } else if token.text == close_text {
+            depth -= 1;
+            if depth == 0 {
+                return i;
+            }
+        }
+    }
+    tokens.len()
+}
+
+/// Drops a leading `template <...>` and specifiers.
+fn declaration_start(stmt: &[Token]) -> usize {
+    let mut i = 0;
+    while i < stmt.len() {
+        if stmt[i].text == "template" && stmt.get(i + 1).is_some_and(|t| t.text == "<") {
+            // An unclosed `template <` takes the whole statement.
+            i = (matching(stmt, i + 1, "<", ">") + 1).min(stmt.len());
+        } else if SPECIFIERS.contains(&stmt[i].text) {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    i
+}
+
+/// Position of the first `(` outside template arguments.
+fn paren(stmt: &[Token]) -> Option<usize> {
+    let mut angle = 0;
+    for (i, token) in stmt.iter().enumerate() {
+        match token.text {
+            "<" => angle += 1,
+            ">" if angle > 0 => angle -= 1,
+            "(" if angle == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits at commas outside brackets.
+fn split_commas<'t, 'a>(tokens: &'t [Token<'a>]) -> Vec<&'t [Token<'a>]> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0i32, 0);
+    for (i, token) in tokens.iter().enumerate() {
+        match token.text {
+            "<" | "(" | "[" | "{" => depth += 1,
+            ">" | ")" | "]" | "}" => depth -= 1,
+            "," if depth == 0 => {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&tokens[start..]);
+    parts
+}
+
+fn signature(source: &str, stmt: &[Token]) -> String {
+    match (stmt.first(), stmt.last()) {
+        (Some(first), Some(last)) => {
+            source[first.start..last.start + last.text.len()].split_whitespace().collect::<Vec<_>>().join(" ")
+        }
+        _ => String::new(),
+    }
+}
+
+enum Scope {
+    Global,
+    Record(String),
+    Namespace(String),
+    /// A function body or anything else whose contents are not indexed.
+    Block,
+    /// Braces inside a declaration, as in `vector<int> v{1, 2};`.
+    Initializer,
+}
+
+impl Index {
+    /// Indexes `source`, a whole C++ file.
+    pub fn build(source: &str) -> Index {
+        let text = strip(source);
+        let tokens = tokenize(&text);
+        let mut index = Index::default();
+        let mut scopes = vec![Scope::Global];
+        let mut start = 0;
+        for (i, token) in tokens.iter().enumerate() {
+            let stmt = &tokens[start..i];
+            match token.text {
+                "{" => {
+                    let scope = index.open(source, stmt, scopes.last().unwrap_or(&Scope::Global));
+                    if !matches!(scope, Scope::Initializer) {
+                        start = i + 1;
+                    }
+                    scopes.push(scope);
+                }
+                "}" => {
+                    if !matches!(scopes.pop(), Some(Scope::Initializer)) {
+                        start = i + 1;
+                    }
+                    if scopes.is_empty() {
+                        scopes.push(Scope::Global);
+                    }
+                }
+                ";" => {
+                    index.declare(source, stmt, scopes.last().unwrap_or(&Scope::Global));
+                    start = i + 1;
+                }
+                ":" if matches!(stmt, [t] if ["public", "private", "protected"].contains(&t.text)) => {
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        index
+    }
+
+    fn scope_members(&mut self, scope: &Scope) -> Option<&mut Vec<Member>> {
+        match scope {
+            Scope::Record(name) => Some(self.records.entry(name.clone()).or_default()),
+            Scope::Namespace(name) => Some(self.namespaces.entry(name.clone()).or_default()),
+            _ => None,
+        }
+    }
+
+    // What the `{` after `stmt` opens.
+    fn open(&mut self, source: &str, stmt: &[Token], scope: &Scope) -> Scope {
+        if matches!(scope, Scope::Initializer) {
+            return Scope::Initializer;
+        }
+        let decl = &stmt[declaration_start(stmt)..];
+        let first = decl.first().map_or("", |t| t.text);
+        if first == "namespace" {
+            return Scope::Namespace(decl.get(1).map_or("", |t| t.text).to_string());
+        }
+        if ["struct", "class", "union"].contains(&first) {
+            if let Some(name) = decl.get(1).filter(|t| is_ident(t.text)) {
+                let member = Member {
+                    name: name.text.to_string(),
+                    kind: MemberKind::Type,
+                    ty: name.text.to_string(),
+                    signature: signature(source, decl),
+                };
+                if let Some(members) = self.scope_members(scope) {
+                    members.push(member);
+                }
+                self.records.entry(name.text.to_string()).or_default();
+                return Scope::Record(name.text.to_string());
+            }
+            return Scope::Block;
+        }
+        if first == "enum" {
+            return Scope::Block;
+        }
+        match paren(decl) {
+            Some(_) => {
+                // A function or method body.
+                self.declare(source, stmt, scope);
+                Scope::Block
+            }
+            None if decl.last().is_some_and(|t| is_ident(t.text) || t.text == "=" || t.text == "]") => {
+                Scope::Initializer
+            }
+            None => Scope::Block,
+        }
+    }
+
+    // Records the declaration `stmt`, which ended in `;` or a body.
+    fn declare(&mut self, source: &str, stmt: &[Token], scope: &Scope) {
+        let decl = &stmt[declaration_start(stmt)..];
+        let Some(first) = decl.first() else {
+            return;
+        };
+
+        // Aliases count wherever they are, even inside functions.
+        if first.text == "using" && decl.get(2).is_some_and(|t| t.text == "=") {
+            self.aliases.insert(decl[1].text.to_string(), join(&decl[3..]));
+            if let Some(members) = self.scope_members(scope) {
+                members.push(Member {
+                    name: decl[1].text.to_string(),
+                    kind: MemberKind::Type,
+                    ty: join(&decl[3..]),
+                    signature: signature(source, decl),
+                });
+            }
+            return;
+        }
+        if first.text == "typedef" && decl.len() > 2 {
+            let name = decl[decl.len() - 1].text;
+            if is_ident(name) {
+                self.aliases.insert(name.to_string(), join(&decl[1..decl.len() - 1]));
+            }
+            return;
+        }
+        if matches!(scope, Scope::Block | Scope::Initializer)
+            || ["friend", "return", "using", "typedef", "struct", "class", "enum"].contains(&first.text)
+        {
+            return;
+        }
+
+        let record = match scope {
+            Scope::Record(name) => Some(name.as_str()),
+            _ => None,
+        };
+        let mut members = Vec::new();
+        if let Some(open) = paren(decl) {
+            let Some(name) = open.checked_sub(1).map(|i| &decl[i]).filter(|t| is_ident(t.text)) else {
+                return;
+            };
+            let before = &decl[..open - 1];
+            let constructor = before.is_empty() || before.last().is_some_and(|t| t.text == "~" || t.text == "operator");
+            if constructor || Some(name.text) == record || name.text == "operator" {
+                return;
+            }
+            let close = matching(decl, open, "(", ")").min(decl.len() - 1);
+            let signature = signature(source, &decl[..=close]);
+            if record.is_none() {
+                self.functions.insert(name.text.to_string(), signature.clone());
+            }
+            members.push(Member { name: name.text.to_string(), kind: MemberKind::Method, ty: join(before), signature });
+        } else {
+            let parts = split_commas(decl);
+            let mut ty = String::new();
+            for (i, part) in parts.iter().enumerate() {
+                let end = part.iter().position(|t| ["=", "[", "{", ":"].contains(&t.text)).unwrap_or(part.len());
+                let Some(name) = part[..end].last().filter(|t| is_ident(t.text)) else {
+                    continue;
+                };
+                if i == 0 {
+                    let type_tokens: Vec<Token> =
+                        part[..end - 1].iter().filter(|t| t.text != "*" && t.text != "&").copied().collect();
+                    if type_tokens.is_empty() {
+                        return;
+                    }
+                    ty = join(&type_tokens);
+                }
+                // `*` belongs to each name, as in `Node *next, *prev;`.
+                let pointer = "*".repeat(part[..end - 1].iter().filter(|t| t.text == "*").count());
+                let field_type = format!("{}{}", ty, pointer);
+                members.push(Member {
+                    name: name.text.to_string(),
+                    kind: MemberKind::Field,
+                    signature: format!("{} {}", field_type, name.text),
+                    ty: field_type,
+                });
+            }
+        }
+        if let Some(scope_members) = self.scope_members(scope) {
+            scope_members.extend(members);
+        }
+    }
+
+    /// `ty` with aliases replaced and qualifiers dropped: `const vi&`
+    /// becomes `vector<int>`.
+    pub fn resolve(&self, ty: &str) -> String {
+        let mut ty = ty.trim().to_string();
+        for _ in 0..8 {
+            for prefix in ["const ", "std::", "typename "] {
+                while let Some(rest) = ty.strip_prefix(prefix) {
+                    ty = rest.trim_start().to_string();
+                }
+            }
+            ty = ty.replace("std::", "").trim_end_matches(['&', ' ']).to_string();
+            let (base, rest) = ty.split_at(ty.find(['<', '*', '[']).unwrap_or(ty.len()));
+            match self.aliases.get(base.trim()) {
+                Some(target) => ty = format!("{}{}", target, rest),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    /// The members offered after `ty.` (or `ty->` when `pointer`).
+    pub fn members_of(&self, ty: &str, pointer: bool) -> Vec<Member> {
+        let ty = self.resolve(ty);
+        let ty = if pointer {
+            match ty.strip_suffix('*') {
+                Some(pointee) => pointee.trim_end().to_string(),
+                None => return Vec::new(),
+            }
+        } else if ty.ends_with('*') || ty.ends_with("[]") {
+            return Vec::new();
+        } else {
+            ty
+        };
+        let base = ty.split('<').next().unwrap_or("").trim();
+        // Records are indexed by their own name, whatever namespace they
+        // are in.
+        let record = base.rsplit("::").next().unwrap_or(base);
+        if let Some(members) = self.records.get(record) {
+            return members.clone();
+        }
+        let args = template_args(&ty);
+        stl::members(base)
+            .iter()
+            .map(|&(name, signature)| Member {
+                name: name.to_string(),
+                kind: if signature.contains('(') { MemberKind::Method } else { MemberKind::Field },
+                ty: stl::field_type(base, name, &args).unwrap_or_default(),
+                signature: signature.to_string(),
+            })
+            .collect()
+    }
+
+    /// What `name::` offers: a namespace's contents, a class's members,
+    /// or the standard library for `std`.
+    pub fn scope_contents(&self, name: &str) -> Vec<Member> {
+        if let Some(members) = self.namespaces.get(name) {
+            return members.clone();
+        }
+        if name == "std" {
+            return stl::STD_NAMES
+                .iter()
+                .map(|&name| Member {
+                    name: name.to_string(),
+                    kind: MemberKind::Type,
+                    ty: String::new(),
+                    signature: String::new(),
+                })
+                .collect();
+        }
+        self.records.get(&self.resolve(name)).cloned().unwrap_or_default()
+    }
+}
+
+/// The top-level template arguments of `ty`: `map<int, vector<int>>` has
+/// `int` and `vector<int>`.
+pub fn template_args(ty: &str) -> Vec<String> {
+    let (Some(open), Some(close)) = (ty.find('<'), ty.rfind('>')) else {
+        return Vec::new();
+    };
+    if close <= open {
+        return Vec::new();
+    }
+    let mut args = Vec::new();
+    let (mut depth, mut start) = (0, open + 1);
+    for (i, c) in ty.char_indices().take(close).skip(open + 1) {
+        match c {
+            '<' | '(' => depth += 1,
+            '>' | ')' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(ty[start..i].trim().to_string());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    args.push(ty[start..close].trim().to_string());
+    args
+}
+
+/// The type of the expression ending just before `source[..end]`'s
+/// trailing `.`, `->` or `::` (which is not included in `end`), as far as
+/// a chain of names, subscripts and field accesses like `g[v].first` can
+/// be followed, together with the accessor. For `::` the "type" is the
+/// scope name itself.
+pub fn expression_type(index: &Index, source: &str, end: usize) -> Option<(String, &'static str)> {
+    let text = strip(&source[..end]);
+    let tokens = tokenize(&text);
+    let accessor = match tokens.last()?.text {
+        "." => ".",
+        "->" => "->",
+        "::" => "::",
+        _ => return None,
+    };
+    let mut i = tokens.len() - 1;
+
+    // Walk back over the chain: name ([...])* ((. | ->) name ([...])*)*
+    let mut steps: Vec<(&str, usize, &str)> = Vec::new();
+    loop {
+        let mut subscripts = 0;
+        while i > 0 && tokens[i - 1].text == "]" {
+            let mut depth = 0;
+            let mut j = i - 1;
+            loop {
+                match tokens[j].text {
+                    "]" => depth += 1,
+                    "[" => depth -= 1,
+                    _ => {}
+                }
+                if depth == 0 || j == 0 {
+                    break;
+                }
+                j -= 1;
+            }
+            subscripts += 1;
+            i = j;
+        }
+        let name = tokens.get(i.checked_sub(1)?).filter(|t| is_ident(t.text))?;
+        i -= 1;
+        let via = match i.checked_sub(1).map(|j| tokens[j].text) {
+            Some(op @ ("." | "->")) if accessor != "::" => op,
+            _ => "",
+        };
+        steps.push((name.text, subscripts, via));
+        if via.is_empty() {
+            break;
+        }
+        i -= 1;
+    }
+    steps.reverse();
+
+    if accessor == "::" {
+        return Some((steps.last()?.0.to_string(), accessor));
+    }
+    let (name, subscripts, _) = steps[0];
+    let mut ty = if name == "this" {
+        enclosing_record(&tokens[..i])? + "*"
+    } else {
+        variable_type(index, &tokens[..i], name)?
+    };
+    for _ in 0..subscripts {
+        ty = stl::subscript_type(&index.resolve(&ty))?;
+    }
+    for &(name, subscripts, via) in &steps[1..] {
+        let member = index.members_of(&ty, via == "->").into_iter().find(|m| m.name == name && m.kind == MemberKind::Field)?;
+        ty = member.ty;
+        for _ in 0..subscripts {
+            ty = stl::subscript_type(&index.resolve(&ty))?;
+        }
+    }
+    Some((ty, accessor))
+}
+
+// The struct or class whose body the end of `tokens` is in.
+fn enclosing_record(tokens: &[Token]) -> Option<String> {
+    let mut depth = 0;
+    for i in (0..tokens.len()).rev() {
+        match tokens[i].text {
+            "}" => depth += 1,
+            "{" if depth > 0 => depth -= 1,
+            "{" => {
+                let start = tokens[..i].iter().rposition(|t| [";", "{", "}"].contains(&t.text)).map_or(0, |p| p + 1);
+                let stmt = &tokens[start..i];
+                let at = stmt.iter().position(|t| ["struct", "class"].contains(&t.text));
+                if let Some(name) = at.and_then(|at| stmt.get(at + 1)) {
+                    return Some(name.text.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Words that can come right before a name without being its type.
+const NOT_TYPES: &[&str] = &["return", "case", "delete", "new", "throw", "else", "do", "goto", "sizeof", "co_return"];
+
+/// The declared type of variable `name`, from its last declaration in
+/// `tokens`: a variable, parameter or range-for variable. `auto` is only
+/// understood in range-for loops.
+fn variable_type(index: &Index, tokens: &[Token], name: &str) -> Option<String> {
+    for i in (1..tokens.len()).rev() {
+        if tokens[i].text != name {
+            continue;
+        }
+        let next = tokens.get(i + 1).map_or(";", |t| t.text);
+        if ![";", "=", "(", "{", "[", ",", ")", ":"].contains(&next) {
+            continue;
+        }
+        let mut j = i;
+        let mut pointer = String::new();
+        while j > 0 && ["*", "&"].contains(&tokens[j - 1].text) {
+            if tokens[j - 1].text == "*" {
+                pointer.push('*');
+            }
+            j -= 1;
+        }
+        let Some(end) = j.checked_sub(1) else {
+            continue;
+        };
+        let last = tokens[end].text;
+        if !(last == ">" || is_ident(last)) || NOT_TYPES.contains(&last) {
+            continue;
+        }
+        // Walk back over the type: names joined by `::`, template
+        // arguments and multi-word built-in types.
+        let mut k = end;
+        loop {
+            if tokens[k].text == ">" {
+                let mut depth = 0;
+                while k > 0 {
+                    match tokens[k].text {
+                        ">" => depth += 1,
+                        "<" => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    k -= 1;
+                }
+                k = k.checked_sub(1)?;
+            }
+            if k > 1 && tokens[k - 1].text == "::" {
+                k -= 2;
+            } else if k > 0 && is_ident(tokens[k - 1].text) && is_builtin_word(tokens[k].text) && is_builtin_word(tokens[k - 1].text) {
+                k -= 1;
+            } else {
+                break;
+            }
+        }
+        if k > 0 && ([".", "->"].contains(&tokens[k - 1].text)) {
+            continue;
+        }
+        let ty = join(&tokens[k..=end]);
+        let ty = format!("{}{}{}", ty, pointer, if next == "[" { "[]" } else { "" });
+        if ty == "auto" {
+            // for (auto& x : container)
+            if next == ":" {
+                let container = tokens.get(i + 2).filter(|t| is_ident(t.text))?;
+                let mut container_type = variable_type(index, &tokens[..i], container.text)?;
+                let mut k = i + 3;
+                while tokens.get(k).is_some_and(|t| t.text == "[") {
+                    container_type = stl::subscript_type(&index.resolve(&container_type))?;
+                    k = matching(tokens, k, "[", "]") + 1;
+                }
+                return stl::element_type(&index.resolve(&container_type));
+            }
+            continue;
+        }
+        return Some(ty);
+    }
+    None
+}
+
+fn is_builtin_word(word: &str) -> bool {
+    ["unsigned", "signed", "long", "short", "int", "char", "double", "const"].contains(&word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+#include <vector>
+using namespace std;
+typedef long long ll;
+using vi = vector<int>;
+
+namespace geo {
+struct Point {
+    ll x, y;
+    Point operator+(const Point& o) const { return {x + o.x, y + o.y}; }
+    ll norm() const;
+};
+}
+
+template <typename T>
+struct Node {
+    T value;
+    Node* next = nullptr;
+    vector<pair<int, T>> edges;
+};
+
+int solve(const vi& a, int k) {
+    return 0;
+}
+"#;
+
+    fn names(members: &[Member]) -> Vec<&str> {
+        members.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    #[test]
+    fn indexes_declarations() {
+        let index = Index::build(SOURCE);
+        assert_eq!(names(&index.records["Point"]), vec!["x", "y", "norm"]);
+        assert_eq!(index.records["Point"][1].ty, "ll");
+        assert_eq!(names(&index.records["Node"]), vec!["value", "next", "edges"]);
+        assert_eq!(index.records["Node"][1].ty, "Node*");
+        assert_eq!(names(&index.namespaces["geo"]), vec!["Point"]);
+        assert_eq!(index.aliases["ll"], "long long");
+        assert_eq!(index.resolve("const vi&"), "vector<int>");
+        assert_eq!(index.functions["solve"], "int solve(const vi& a, int k)");
+    }
+
+    #[test]
+    fn survives_unfinished_code() {
+        for source in ["template <typename T\nint main() {\n}\n", "template <", "struct {", "}}}", "int f(;", "using = ;"] {
+            Index::build(source);
+        }
+    }
+
+    #[test]
+    fn finds_variable_types() {
+        let cases = [
+            ("vector<int> v;", "v", Some("vector<int>")),
+            ("std::map<string, vector<int>> m = {};", "m", Some("std::map<string,vector<int>>")),
+            ("void f(const Point& p, int n) {", "p", Some("Point")),
+            ("Node<int> *head, *tail;", "head", Some("Node<int>*")),
+            ("int a[10];", "a", Some("int[]")),
+            ("unsigned long long big = 0;", "big", Some("unsigned long long")),
+            ("vector<pair<int, int>> es; for (auto& [u, w] : es) {} for (auto& e : es) {", "e", Some("pair<int,int>")),
+            ("return x;", "x", None),
+            ("p.x = 1;", "x", None),
+        ];
+        let index = Index::default();
+        for (source, name, expected) in cases {
+            let text = strip(source);
+            let tokens = tokenize(&text);
+            assert_eq!(variable_type(&index, &tokens, name).as_deref(), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn follows_expressions() {
+        let index = Index::build(SOURCE);
+        let body = "\nint main() {\n    vector<Node<int>> g(5);\n    geo::Point p;\n    Node<int>* head;\n    ";
+        let cases = [
+            ("p.", Some(("geo::Point", "."))),
+            ("head->", Some(("Node<int>*", "->"))),
+            ("g[0].next->", Some(("Node*", "->"))),
+            ("g[0].edges[1].", Some(("pair<int,T>", "."))),
+            ("geo::", Some(("geo", "::"))),
+            ("unknown.", None),
+            ("p", None),
+        ];
+        for (typed, expected) in cases {
+            let source = format!("{}{}{}", SOURCE, body, typed);
+            let found = expression_type(&index, &source, source.len());
+            assert_eq!(found.as_ref().map(|(ty, accessor)| (ty.as_str(), *accessor)), expected, "{}", typed);
+        }
+        assert_eq!(names(&index.members_of("geo::Point", false)), vec!["x", "y", "norm"]);
+        assert_eq!(names(&index.members_of("Node<int>*", true)), vec!["value", "next", "edges"]);
+    }
+}
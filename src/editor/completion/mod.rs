@@ -0,0 +1,334 @@
+//! Completion candidates and the popup menu that shows them.
+
+use cursive::theme::{BaseColor, Color, ColorStyle};
+use cursive::Printer;
+use std::collections::HashMap;
+
+pub mod index;
+pub mod sources;
+pub mod stl;
+
+#[cfg(test)]
+mod tests;
+
+/// Where a completion candidate comes from, shown next to it in the menu.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Keyword,
+    Snippet,
+    /// One of the values a snippet placeholder offers.
+    Choice,
+    /// A word from the buffer or an included header.
+    Identifier,
+    /// A member after `.`, `->` or `::`.
+    Member,
+    /// A whole line of the buffer.
+    Line,
+    File,
+}
+
+impl Kind {
+    pub fn label(self) -> &'static str {
+        match self {
+            Kind::Keyword => "keyword",
+            Kind::Snippet => "snippet",
+            Kind::Choice => "choice",
+            Kind::Identifier => "identifier",
+            Kind::Member => "member",
+            Kind::Line => "line",
+            Kind::File => "file",
+        }
+    }
+
+    /// Added to the score of candidates from this source, so equally good
+    /// matches from more specific sources come first.
+    fn priority(self) -> i32 {
+        match self {
+            Kind::Choice | Kind::Member => 30,
+            Kind::Identifier => 25,
+            Kind::Snippet => 20,
+            Kind::Keyword => 10,
+            Kind::Line | Kind::File => 0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Item {
+    /// The text that replaces the word before the cursor.
+    pub word: String,
+    pub kind: Kind,
+    /// Extra text shown after the kind, such as a snippet's description.
+    pub menu: String,
+    /// Shown in the preview pane while the item is selected: a snippet's
+    /// body or a symbol's signature.
+    pub info: String,
+}
+
+impl Item {
+    pub fn new(word: &str, kind: Kind) -> Self {
+        Item { word: word.to_string(), kind, menu: String::new(), info: String::new() }
+    }
+}
+
+// Fuzzy match scoring: every matched character earns MATCH, more if it
+// follows the previous match or starts a word; skipped characters cost
+// GAP each, up to MAX_GAP per gap, and more before the first match.
+const MATCH: i32 = 16;
+const CONSECUTIVE: i32 = 15;
+const WORD_START: i32 = 10;
+const FIRST_CHAR: i32 = 20;
+const GAP: i32 = 1;
+const MAX_GAP: i32 = 8;
+const LEADING_GAP: i32 = 3;
+const MAX_LEADING_GAP: i32 = 15;
+
+fn is_word_start(chars: &[char], i: usize) -> bool {
+    i == 0 || !chars[i - 1].is_alphanumeric() || (chars[i - 1].is_lowercase() && chars[i].is_uppercase())
+}
+
+/// How well `pattern` matches `candidate` as a subsequence (`bsr` matches
+/// `binarysearch`), or `None` if it does not. Higher is better: prefixes,
+/// runs of adjacent characters and matches at word starts (after `_` or
+/// at a camelCase hump) score highest. Matching ignores case unless the
+/// pattern has an uppercase letter.
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i32> {
+    let ignore_case = !pattern.chars().any(char::is_uppercase);
+    let fold = |c: char| if ignore_case { c.to_ascii_lowercase() } else { c };
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    if pattern.is_empty() || pattern.len() > chars.len() {
+        return pattern.is_empty().then_some(0);
+    }
+
+    // best[j]: best score with the pattern so far matched and its last
+    // character at candidate position j.
+    let mut best: Vec<Option<i32>> = vec![None; chars.len()];
+    for (i, &p) in pattern.iter().enumerate() {
+        let mut next = vec![None; chars.len()];
+        for j in i..chars.len() {
+            if fold(chars[j]) != p {
+                continue;
+            }
+            let mut bonus = MATCH;
+            if is_word_start(&chars, j) {
+                bonus += if j == 0 { FIRST_CHAR } else { WORD_START };
+            }
+            next[j] = if i == 0 {
+                Some(bonus - (j as i32 * LEADING_GAP).min(MAX_LEADING_GAP))
+            } else {
+                (i - 1..j)
+                    .filter_map(|k| {
+                        let gap = (j - k - 1) as i32;
+                        let adjacent = if gap == 0 { CONSECUTIVE } else { -(gap * GAP).min(MAX_GAP) };
+                        best[k].map(|score| score + bonus + adjacent)
+                    })
+                    .max()
+            };
+        }
+        best = next;
+    }
+    // Shorter candidates win ties.
+    best.into_iter().flatten().max().map(|score| score - (chars.len() - pattern.len()) as i32 / 4)
+}
+
+/// Which completions were accepted, how often and how recently, to rank
+/// them higher next time.
+#[derive(Default)]
+pub struct History {
+    accepted: HashMap<String, (u32, u64)>,
+    clock: u64,
+}
+
+impl History {
+    pub fn record(&mut self, word: &str) {
+        self.clock += 1;
+        let entry = self.accepted.entry(word.to_string()).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 = self.clock;
+    }
+
+    /// Score bonus for `word`: up to 40 for frequency and 24 for having
+    /// been accepted in the last few completions.
+    fn bonus(&self, word: &str) -> i32 {
+        self.accepted.get(word).map_or(0, |&(count, last)| {
+            let age = (self.clock - last) as i32;
+            4 * count.min(10) as i32 + 24 * 8 / (8 + age)
+        })
+    }
+}
+
+/// The candidates `pattern` fuzzily matches, best first: by match quality,
+/// then `history`, then source. Equal scores fall back to the shorter and
+/// then alphabetically first word, so the order is stable.
+pub fn rank(pattern: &str, candidates: Vec<Item>, history: &History) -> Vec<Item> {
+    let mut scored: Vec<(i32, Item)> = candidates
+        .into_iter()
+        .filter_map(|item| {
+            let score = fuzzy_score(pattern, &item.word)?;
+            Some((score + history.bonus(&item.word) + item.kind.priority(), item))
+        })
+        .collect();
+    scored.sort_by(|(a, x), (b, y)| {
+        b.cmp(a)
+            .then(x.word.len().cmp(&y.word.len()))
+            .then_with(|| x.word.cmp(&y.word))
+            .then(y.kind.priority().cmp(&x.kind.priority()))
+    });
+    scored.dedup_by(|(_, x), (_, y)| x.word == y.word && x.kind == y.kind);
+    scored.into_iter().map(|(_, item)| item).collect()
+}
+
+// Widest the word and menu columns get before they are cut short.
+const MAX_WORD_WIDTH: usize = 40;
+const MAX_MENU_WIDTH: usize = 30;
+// The preview pane is not shown narrower than this.
+const MIN_PREVIEW_WIDTH: usize = 20;
+const MAX_PREVIEW_WIDTH: usize = 60;
+
+/// The menu's position on screen: `height` rows from `y`, `width` columns
+/// from `x`, above or below the cursor line.
+#[derive(Clone, Copy)]
+pub struct Placement {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub above: bool,
+}
+
+fn column_widths(items: &[Item]) -> (usize, usize) {
+    let word = items.iter().map(|i| i.word.chars().count()).max().unwrap_or(0).min(MAX_WORD_WIDTH);
+    let menu = items.iter().map(|i| i.menu.chars().count()).max().unwrap_or(0).min(MAX_MENU_WIDTH);
+    (word, menu)
+}
+
+fn kind_width(items: &[Item]) -> usize {
+    items.iter().map(|i| i.kind.label().len()).max().unwrap_or(0)
+}
+
+/// Places the menu for a word starting at screen column `x` of row `y` in
+/// a text area of `area` columns and rows. It opens below the line, unless
+/// it does not fit there and there is more room above. `max_rows` limits
+/// its height unless 0.
+pub fn place(items: &[Item], max_rows: usize, (x, y): (usize, usize), area: (usize, usize)) -> Placement {
+    let wanted = if max_rows == 0 { items.len() } else { items.len().min(max_rows) };
+    let below = area.1.saturating_sub(y + 1);
+    let above = wanted > below && below < y;
+    let (y, height) = if above {
+        let height = wanted.min(y);
+        (y - height, height)
+    } else {
+        (y + 1, wanted.min(below))
+    };
+
+    let (word, menu) = column_widths(items);
+    let mut width = 1 + word + 2 + kind_width(items) + 1;
+    if menu > 0 {
+        width += 1 + menu + 1;
+    }
+    if items.len() > height {
+        // Scrollbar.
+        width += 1;
+    }
+    let width = width.min(area.0);
+    Placement { x: x.min(area.0 - width), y, width, height, above }
+}
+
+/// The first item shown so that `selected` is visible, moving `top` as
+/// little as possible.
+pub fn scroll(top: usize, selected: usize, height: usize, len: usize) -> usize {
+    let top = if selected < top { selected } else { top.max((selected + 1).saturating_sub(height)) };
+    top.min(len.saturating_sub(height))
+}
+
+// `text` cut or padded to exactly `width` columns.
+fn fit(text: &str, width: usize) -> String {
+    let text: String = text.chars().take(width).collect();
+    format!("{:width$}", text)
+}
+
+/// Draws the menu with `items[top..]` in `placement`, and the preview pane
+/// for the selected item beside it if it has one. The pane goes on
+/// whichever side of the menu has room, within `area`.
+pub fn draw(printer: &Printer, items: &[Item], selected: usize, top: usize, placement: Placement, area: (usize, usize)) {
+    if placement.height == 0 {
+        return;
+    }
+    let normal = ColorStyle::new(Color::Dark(BaseColor::Black), Color::Light(BaseColor::White));
+    let current = ColorStyle::new(Color::Light(BaseColor::White), Color::Dark(BaseColor::Blue));
+    let label = ColorStyle::new(Color::Dark(BaseColor::Magenta), Color::Light(BaseColor::White));
+    let scrollbar = items.len() > placement.height;
+    let (word_width, menu_width) = column_widths(items);
+    let kind_width = kind_width(items);
+    let text_width = placement.width - usize::from(scrollbar);
+
+    for row in 0..placement.height {
+        let Some(item) = items.get(top + row) else {
+            break;
+        };
+        let y = placement.y + row;
+        let style = if top + row == selected { current } else { normal };
+        let mut line = format!(" {}  ", fit(&item.word, word_width));
+        let kind_at = line.chars().count();
+        line.push_str(&fit(item.kind.label(), kind_width));
+        if menu_width > 0 {
+            line.push_str("  ");
+            line.push_str(&fit(&item.menu, menu_width));
+        }
+        let line = fit(&line, text_width);
+        printer.with_color(style, |printer| printer.print((placement.x, y), &line));
+        if top + row != selected && kind_at < text_width {
+            let kind = fit(item.kind.label(), kind_width.min(text_width - kind_at));
+            printer.with_color(label, |printer| printer.print((placement.x + kind_at, y), &kind));
+        }
+    }
+
+    if scrollbar {
+        let thumb = (placement.height * placement.height / items.len()).max(1);
+        let start = (top * placement.height / items.len()).min(placement.height - thumb);
+        let x = placement.x + placement.width - 1;
+        for row in 0..placement.height {
+            let style = if (start..start + thumb).contains(&row) {
+                ColorStyle::new(Color::Dark(BaseColor::Blue), Color::Dark(BaseColor::Blue))
+            } else {
+                ColorStyle::new(Color::Dark(BaseColor::White), Color::Dark(BaseColor::White))
+            };
+            printer.with_color(style, |printer| printer.print((x, placement.y + row), " "));
+        }
+    }
+
+    if let Some(item) = items.get(selected).filter(|item| !item.info.is_empty()) {
+        draw_preview(printer, &item.info, placement, area);
+    }
+}
+
+fn draw_preview(printer: &Printer, info: &str, menu: Placement, area: (usize, usize)) {
+    let lines: Vec<&str> = info.lines().collect();
+    let wanted = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0).min(MAX_PREVIEW_WIDTH) + 2;
+    let right = area.0.saturating_sub(menu.x + menu.width);
+    let (x, width) = if right >= wanted.min(MIN_PREVIEW_WIDTH) || right >= menu.x {
+        (menu.x + menu.width, wanted.min(right))
+    } else {
+        let width = wanted.min(menu.x);
+        (menu.x - width, width)
+    };
+    if width < 3 {
+        return;
+    }
+    // Stay on the menu's side of the cursor line.
+    let (y, height) = if menu.above {
+        let height = lines.len().min(menu.y + menu.height);
+        (menu.y + menu.height - height, height)
+    } else {
+        let height = lines.len().min(area.1 - menu.y);
+        (menu.y, height)
+    };
+
+    let style = ColorStyle::new(Color::Light(BaseColor::White), Color::Dark(BaseColor::Black));
+    printer.with_color(style, |printer| {
+        for (row, line) in lines.iter().take(height).enumerate() {
+            printer.print((x, y + row), &format!(" {} ", fit(line, width - 2)));
+        }
+    });
+}
@@ -0,0 +1,134 @@
+//! Completion candidates gathered from text: words in the buffer and the
+//! local headers it includes, whole lines, and file names.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The identifiers in `line`, in order.
+pub fn words(line: &str) -> impl Iterator<Item = &str> {
+    line.split(|c: char| !is_word_char(c))
+        .filter(|word| !word.is_empty() && !word.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+// Line numbers of `lines` by distance from `y`, lines below first among
+// equally distant ones if `below_first`.
+fn nearest_first(count: usize, y: usize, below_first: bool) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..count).collect();
+    order.sort_by_key(|&line| (line.abs_diff(y), (line < y) == below_first));
+    order
+}
+
+/// Words of `lines` that `matches` accepts, each once, the ones nearest
+/// to line `y` first. The word touching column `x` of line `y`, the one
+/// being typed, is left out.
+pub fn nearest_words(lines: &[String], (x, y): (usize, usize), matches: &dyn Fn(&str) -> bool, below_first: bool) -> Vec<String> {
+    let order = nearest_first(lines.len(), y, below_first);
+    let mut seen = HashSet::new();
+    let mut found = Vec::new();
+    for line in order {
+        let text = &lines[line];
+        for word in words(text) {
+            let start = word.as_ptr() as usize - text.as_ptr() as usize;
+            if line == y && (start..=start + word.len()).contains(&x) {
+                continue;
+            }
+            if matches(word) && seen.insert(word) {
+                found.push(word.to_string());
+            }
+        }
+    }
+    found
+}
+
+/// Local headers (`#include "..."`) of a file in `dir` that exist on disk.
+pub fn local_headers(lines: &[String], dir: &Path) -> Vec<PathBuf> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let rest = line.trim().strip_prefix('#')?.trim_start().strip_prefix("include")?.trim_start();
+            let name = rest.strip_prefix('"')?.split('"').next()?;
+            Some(dir.join(name))
+        })
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// The words of local headers, read again only when a header changes on
+/// disk.
+#[derive(Default)]
+pub struct HeaderWords {
+    files: HashMap<PathBuf, (SystemTime, Vec<String>)>,
+}
+
+impl HeaderWords {
+    /// The words of the header at `path`, each once, in file order.
+    pub fn get(&mut self, path: &Path) -> &[String] {
+        let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) else {
+            return &[];
+        };
+        if self.files.get(path).is_none_or(|(read, _)| *read != modified) {
+            let lines: Vec<String> = fs::read_to_string(path).unwrap_or_default().lines().map(String::from).collect();
+            let words = nearest_words(&lines, (usize::MAX, 0), &|_| true, true);
+            self.files.insert(path.to_path_buf(), (modified, words));
+        }
+        &self.files[path].1
+    }
+}
+
+/// Lines of `lines` other than line `y` that start with `prefix` once
+/// indentation is ignored, without their indentation, nearest first.
+pub fn matching_lines(lines: &[String], y: usize, prefix: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    nearest_first(lines.len(), y, false)
+        .into_iter()
+        .filter(|&line| line != y)
+        .map(|line| lines[line].trim())
+        .filter(|text| !text.is_empty() && text.starts_with(prefix) && *text != prefix && seen.insert(*text))
+        .map(String::from)
+        .collect()
+}
+
+/// The file name being typed at the end of `text`: everything after the
+/// last space, quote or angle bracket.
+pub fn path_before(text: &str) -> &str {
+    let start = text.rfind([' ', '\t', '"', '\'', '<', '>', '(', '=']).map_or(0, |i| i + 1);
+    &text[start..]
+}
+
+/// Entries of the directory `path` names up to its last `/` (the current
+/// directory if none, `~` for home) whose names start with the rest, with
+/// a `/` after directories. Hidden files only show if the name being typed
+/// starts with a dot.
+pub fn file_names(path: &str) -> Vec<String> {
+    let (dir, prefix) = match path.rfind('/') {
+        Some(i) => (&path[..=i], &path[i + 1..]),
+        None => ("", path),
+    };
+    let dir_path = match dir.strip_prefix("~/") {
+        Some(rest) => std::env::var_os("HOME").map_or_else(|| PathBuf::from(dir), |home| Path::new(&home).join(rest)),
+        None if dir.is_empty() => PathBuf::from("."),
+        None => PathBuf::from(dir),
+    };
+    let Ok(entries) = fs::read_dir(&dir_path) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}", name, slash))
+        })
+        .collect();
+    names.sort();
+    names
+}
@@ -0,0 +1,310 @@
+//! Members of the standard library types that come up in competitive
+//! programming, and the names in `std`, for member completion.
+
+use crate::editor::completion::index::template_args;
+
+const VECTOR: &[(&str, &str)] = &[
+    ("assign", "void assign(size_type n, const T& value)"),
+    ("at", "T& at(size_type i)"),
+    ("back", "T& back()"),
+    ("begin", "iterator begin()"),
+    ("capacity", "size_type capacity() const"),
+    ("clear", "void clear()"),
+    ("data", "T* data()"),
+    ("emplace", "iterator emplace(const_iterator pos, Args&&... args)"),
+    ("emplace_back", "T& emplace_back(Args&&... args)"),
+    ("empty", "bool empty() const"),
+    ("end", "iterator end()"),
+    ("erase", "iterator erase(const_iterator first, const_iterator last)"),
+    ("front", "T& front()"),
+    ("insert", "iterator insert(const_iterator pos, const T& value)"),
+    ("pop_back", "void pop_back()"),
+    ("push_back", "void push_back(const T& value)"),
+    ("rbegin", "reverse_iterator rbegin()"),
+    ("rend", "reverse_iterator rend()"),
+    ("reserve", "void reserve(size_type n)"),
+    ("resize", "void resize(size_type n, const T& value = T())"),
+    ("shrink_to_fit", "void shrink_to_fit()"),
+    ("size", "size_type size() const"),
+    ("swap", "void swap(vector& other)"),
+];
+
+const DEQUE: &[(&str, &str)] = &[
+    ("assign", "void assign(size_type n, const T& value)"),
+    ("at", "T& at(size_type i)"),
+    ("back", "T& back()"),
+    ("begin", "iterator begin()"),
+    ("clear", "void clear()"),
+    ("emplace_back", "T& emplace_back(Args&&... args)"),
+    ("emplace_front", "T& emplace_front(Args&&... args)"),
+    ("empty", "bool empty() const"),
+    ("end", "iterator end()"),
+    ("erase", "iterator erase(const_iterator first, const_iterator last)"),
+    ("front", "T& front()"),
+    ("insert", "iterator insert(const_iterator pos, const T& value)"),
+    ("pop_back", "void pop_back()"),
+    ("pop_front", "void pop_front()"),
+    ("push_back", "void push_back(const T& value)"),
+    ("push_front", "void push_front(const T& value)"),
+    ("rbegin", "reverse_iterator rbegin()"),
+    ("rend", "reverse_iterator rend()"),
+    ("resize", "void resize(size_type n, const T& value = T())"),
+    ("size", "size_type size() const"),
+    ("swap", "void swap(deque& other)"),
+];
+
+const LIST: &[(&str, &str)] = &[
+    ("back", "T& back()"),
+    ("begin", "iterator begin()"),
+    ("clear", "void clear()"),
+    ("emplace_back", "T& emplace_back(Args&&... args)"),
+    ("emplace_front", "T& emplace_front(Args&&... args)"),
+    ("empty", "bool empty() const"),
+    ("end", "iterator end()"),
+    ("erase", "iterator erase(const_iterator pos)"),
+    ("front", "T& front()"),
+    ("insert", "iterator insert(const_iterator pos, const T& value)"),
+    ("merge", "void merge(list& other)"),
+    ("pop_back", "void pop_back()"),
+    ("pop_front", "void pop_front()"),
+    ("push_back", "void push_back(const T& value)"),
+    ("push_front", "void push_front(const T& value)"),
+    ("remove", "size_type remove(const T& value)"),
+    ("reverse", "void reverse()"),
+    ("size", "size_type size() const"),
+    ("sort", "void sort()"),
+    ("splice", "void splice(const_iterator pos, list& other)"),
+    ("unique", "size_type unique()"),
+];
+
+const STRING: &[(&str, &str)] = &[
+    ("append", "string& append(const string& s)"),
+    ("at", "char& at(size_type i)"),
+    ("back", "char& back()"),
+    ("begin", "iterator begin()"),
+    ("c_str", "const char* c_str() const"),
+    ("clear", "void clear()"),
+    ("compare", "int compare(const string& s) const"),
+    ("empty", "bool empty() const"),
+    ("end", "iterator end()"),
+    ("erase", "string& erase(size_type pos = 0, size_type n = npos)"),
+    ("find", "size_type find(const string& s, size_type pos = 0) const"),
+    ("find_first_of", "size_type find_first_of(const string& chars, size_type pos = 0) const"),
+    ("find_last_of", "size_type find_last_of(const string& chars, size_type pos = npos) const"),
+    ("front", "char& front()"),
+    ("insert", "string& insert(size_type pos, const string& s)"),
+    ("length", "size_type length() const"),
+    ("pop_back", "void pop_back()"),
+    ("push_back", "void push_back(char c)"),
+    ("rbegin", "reverse_iterator rbegin()"),
+    ("rend", "reverse_iterator rend()"),
+    ("replace", "string& replace(size_type pos, size_type n, const string& s)"),
+    ("reserve", "void reserve(size_type n)"),
+    ("resize", "void resize(size_type n, char c = '\\0')"),
+    ("rfind", "size_type rfind(const string& s, size_type pos = npos) const"),
+    ("size", "size_type size() const"),
+    ("substr", "string substr(size_type pos = 0, size_type n = npos) const"),
+    ("swap", "void swap(string& other)"),
+];
+
+const SET: &[(&str, &str)] = &[
+    ("begin", "iterator begin()"),
+    ("clear", "void clear()"),
+    ("contains", "bool contains(const Key& key) const"),
+    ("count", "size_type count(const Key& key) const"),
+    ("emplace", "pair<iterator, bool> emplace(Args&&... args)"),
+    ("empty", "bool empty() const"),
+    ("end", "iterator end()"),
+    ("equal_range", "pair<iterator, iterator> equal_range(const Key& key)"),
+    ("erase", "size_type erase(const Key& key)"),
+    ("extract", "node_type extract(const Key& key)"),
+    ("find", "iterator find(const Key& key)"),
+    ("insert", "pair<iterator, bool> insert(const Key& key)"),
+    ("lower_bound", "iterator lower_bound(const Key& key)"),
+    ("merge", "void merge(set& source)"),
+    ("rbegin", "reverse_iterator rbegin()"),
+    ("rend", "reverse_iterator rend()"),
+    ("size", "size_type size() const"),
+    ("swap", "void swap(set& other)"),
+    ("upper_bound", "iterator upper_bound(const Key& key)"),
+];
+
+const MAP: &[(&str, &str)] = &[
+    ("at", "T& at(const Key& key)"),
+    ("begin", "iterator begin()"),
+    ("clear", "void clear()"),
+    ("contains", "bool contains(const Key& key) const"),
+    ("count", "size_type count(const Key& key) const"),
+    ("emplace", "pair<iterator, bool> emplace(Args&&... args)"),
+    ("empty", "bool empty() const"),
+    ("end", "iterator end()"),
+    ("equal_range", "pair<iterator, iterator> equal_range(const Key& key)"),
+    ("erase", "size_type erase(const Key& key)"),
+    ("find", "iterator find(const Key& key)"),
+    ("insert", "pair<iterator, bool> insert(const value_type& value)"),
+    ("insert_or_assign", "pair<iterator, bool> insert_or_assign(const Key& key, T&& value)"),
+    ("lower_bound", "iterator lower_bound(const Key& key)"),
+    ("rbegin", "reverse_iterator rbegin()"),
+    ("rend", "reverse_iterator rend()"),
+    ("size", "size_type size() const"),
+    ("swap", "void swap(map& other)"),
+    ("try_emplace", "pair<iterator, bool> try_emplace(const Key& key, Args&&... args)"),
+    ("upper_bound", "iterator upper_bound(const Key& key)"),
+];
+
+const UNORDERED: &[(&str, &str)] = &[
+    ("begin", "iterator begin()"),
+    ("bucket_count", "size_type bucket_count() const"),
+    ("clear", "void clear()"),
+    ("contains", "bool contains(const Key& key) const"),
+    ("count", "size_type count(const Key& key) const"),
+    ("emplace", "pair<iterator, bool> emplace(Args&&... args)"),
+    ("empty", "bool empty() const"),
+    ("end", "iterator end()"),
+    ("erase", "size_type erase(const Key& key)"),
+    ("find", "iterator find(const Key& key)"),
+    ("insert", "pair<iterator, bool> insert(const value_type& value)"),
+    ("load_factor", "float load_factor() const"),
+    ("max_load_factor", "void max_load_factor(float ml)"),
+    ("rehash", "void rehash(size_type n)"),
+    ("reserve", "void reserve(size_type n)"),
+    ("size", "size_type size() const"),
+];
+
+const PRIORITY_QUEUE: &[(&str, &str)] = &[
+    ("emplace", "void emplace(Args&&... args)"),
+    ("empty", "bool empty() const"),
+    ("pop", "void pop()"),
+    ("push", "void push(const T& value)"),
+    ("size", "size_type size() const"),
+    ("swap", "void swap(priority_queue& other)"),
+    ("top", "const T& top() const"),
+];
+
+const QUEUE: &[(&str, &str)] = &[
+    ("back", "T& back()"),
+    ("emplace", "void emplace(Args&&... args)"),
+    ("empty", "bool empty() const"),
+    ("front", "T& front()"),
+    ("pop", "void pop()"),
+    ("push", "void push(const T& value)"),
+    ("size", "size_type size() const"),
+    ("swap", "void swap(queue& other)"),
+];
+
+const STACK: &[(&str, &str)] = &[
+    ("emplace", "void emplace(Args&&... args)"),
+    ("empty", "bool empty() const"),
+    ("pop", "void pop()"),
+    ("push", "void push(const T& value)"),
+    ("size", "size_type size() const"),
+    ("swap", "void swap(stack& other)"),
+    ("top", "T& top()"),
+];
+
+const PAIR: &[(&str, &str)] = &[("first", "T1 first"), ("second", "T2 second"), ("swap", "void swap(pair& other)")];
+
+const ARRAY: &[(&str, &str)] = &[
+    ("at", "T& at(size_type i)"),
+    ("back", "T& back()"),
+    ("begin", "iterator begin()"),
+    ("data", "T* data()"),
+    ("empty", "bool empty() const"),
+    ("end", "iterator end()"),
+    ("fill", "void fill(const T& value)"),
+    ("front", "T& front()"),
+    ("rbegin", "reverse_iterator rbegin()"),
+    ("rend", "reverse_iterator rend()"),
+    ("size", "size_type size() const"),
+    ("swap", "void swap(array& other)"),
+];
+
+const BITSET: &[(&str, &str)] = &[
+    ("all", "bool all() const"),
+    ("any", "bool any() const"),
+    ("count", "size_t count() const"),
+    ("flip", "bitset& flip(size_t pos)"),
+    ("none", "bool none() const"),
+    ("reset", "bitset& reset(size_t pos)"),
+    ("set", "bitset& set(size_t pos, bool value = true)"),
+    ("size", "size_t size() const"),
+    ("test", "bool test(size_t pos) const"),
+    ("to_string", "string to_string() const"),
+    ("to_ullong", "unsigned long long to_ullong() const"),
+    ("_Find_first", "size_t _Find_first() const"),
+    ("_Find_next", "size_t _Find_next(size_t prev) const"),
+];
+
+/// The members of the standard type named `base`, which has no namespace
+/// or template arguments.
+pub fn members(base: &str) -> &'static [(&'static str, &'static str)] {
+    match base {
+        "vector" | "basic_string_view" => VECTOR,
+        "deque" => DEQUE,
+        "list" | "forward_list" => LIST,
+        "string" | "wstring" | "basic_string" => STRING,
+        "set" | "multiset" => SET,
+        "map" | "multimap" => MAP,
+        "unordered_map" | "unordered_set" | "unordered_multimap" | "unordered_multiset" => UNORDERED,
+        "priority_queue" => PRIORITY_QUEUE,
+        "queue" => QUEUE,
+        "stack" => STACK,
+        "pair" => PAIR,
+        "array" => ARRAY,
+        "bitset" => BITSET,
+        _ => &[],
+    }
+}
+
+/// The type of a data member of a standard type, given the type's
+/// template arguments: `first` of `pair<int, string>` is `int`.
+pub fn field_type(base: &str, name: &str, args: &[String]) -> Option<String> {
+    match (base, name) {
+        ("pair", "first") => args.first().cloned(),
+        ("pair", "second") => args.get(1).cloned(),
+        _ => None,
+    }
+}
+
+fn base(ty: &str) -> &str {
+    ty.split('<').next().unwrap_or("").trim()
+}
+
+/// The type of `x[i]` for `x` of type `ty`, with aliases already resolved.
+pub fn subscript_type(ty: &str) -> Option<String> {
+    if let Some(element) = ty.strip_suffix("[]").or_else(|| ty.strip_suffix('*')) {
+        return Some(element.trim_end().to_string());
+    }
+    let args = template_args(ty);
+    match base(ty) {
+        "vector" | "deque" | "array" => args.into_iter().next(),
+        "map" | "unordered_map" => args.into_iter().nth(1),
+        "string" => Some("char".to_string()),
+        _ => None,
+    }
+}
+
+/// The type of the elements a range-for loop over `ty` visits.
+pub fn element_type(ty: &str) -> Option<String> {
+    let args = template_args(ty);
+    match base(ty) {
+        "map" | "multimap" | "unordered_map" | "unordered_multimap" if args.len() >= 2 => {
+            Some(format!("pair<{},{}>", args[0], args[1]))
+        }
+        "vector" | "deque" | "list" | "forward_list" | "array" | "set" | "multiset" | "unordered_set"
+        | "unordered_multiset" => args.into_iter().next(),
+        "string" => Some("char".to_string()),
+        _ => ty.strip_suffix("[]").map(|element| element.trim_end().to_string()),
+    }
+}
+
+/// Names in namespace `std` offered after `std::`.
+pub const STD_NAMES: &[&str] = &[
+    "abs", "accumulate", "array", "bitset", "binary_search", "cerr", "cin", "copy", "count", "cout", "deque",
+    "endl", "equal_range", "fill", "find", "function", "gcd", "getline", "greater", "ios", "iota", "lcm", "less",
+    "list", "lower_bound", "make_pair", "make_tuple", "map", "max", "max_element", "memset", "min", "min_element",
+    "minmax", "move", "mt19937", "mt19937_64", "multimap", "multiset", "next_permutation", "nth_element", "numeric_limits",
+    "pair", "partial_sum", "prev_permutation", "priority_queue", "queue", "reverse", "set", "shuffle", "sort",
+    "stable_sort", "stack", "string", "swap", "tie", "to_string", "tuple", "unique", "unordered_map", "unordered_set",
+    "upper_bound", "vector",
+];
@@ -0,0 +1,68 @@
+use super::{fuzzy_score, rank, History, Item, Kind};
+
+fn words(items: &[Item]) -> Vec<&str> {
+    items.iter().map(|i| i.word.as_str()).collect()
+}
+
+fn identifiers(words: &[&str]) -> Vec<Item> {
+    words.iter().map(|w| Item::new(w, Kind::Identifier)).collect()
+}
+
+#[test]
+fn matches_subsequences() {
+    assert!(fuzzy_score("bsr", "binarysearch").is_some());
+    assert!(fuzzy_score("bsr", "binary_search").is_some());
+    assert!(fuzzy_score("vct", "vector").is_some());
+    assert_eq!(fuzzy_score("rsb", "binarysearch"), None);
+    assert_eq!(fuzzy_score("vectors", "vector"), None);
+    assert_eq!(fuzzy_score("", "vector"), Some(0));
+    // An uppercase letter makes the match case sensitive.
+    assert!(fuzzy_score("bs", "binarySearch").is_some());
+    assert!(fuzzy_score("bS", "binarySearch").is_some());
+    assert_eq!(fuzzy_score("BS", "binarySearch"), None);
+}
+
+#[test]
+fn prefixes_beat_gaps() {
+    let score = |pattern, candidate| fuzzy_score(pattern, candidate).unwrap();
+    // A prefix beats the same letters further in or spread out.
+    assert!(score("sort", "sort_by") > score("sort", "is_sorted"));
+    assert!(score("push", "push_back") > score("push", "p_u_s_h"));
+    // Word starts, after `_` or at a camelCase hump, beat letters inside
+    // words.
+    assert!(score("bs", "binary_search") > score("bs", "absolute"));
+    assert!(score("bs", "binarySearch") > score("bs", "bitset_size_hint"));
+    assert!(score("lb", "lower_bound") > score("lb", "label"));
+
+    let ranked = rank("sort", identifiers(&["is_sorted", "sort_by", "sort"]), &History::default());
+    assert_eq!(words(&ranked), vec!["sort", "sort_by", "is_sorted"]);
+}
+
+#[test]
+fn ties_break_on_length_then_alphabet() {
+    let candidates = ["vx", "va", "vec", "v"];
+    let expected = vec!["v", "va", "vx", "vec"];
+    let ranked = rank("v", identifiers(&candidates), &History::default());
+    assert_eq!(words(&ranked), expected);
+    // The input order makes no difference.
+    let mut reversed = candidates;
+    reversed.reverse();
+    assert_eq!(words(&rank("v", identifiers(&reversed), &History::default())), expected);
+}
+
+#[test]
+fn ranks_by_source_and_history() {
+    // The same word from two sources is kept once per source, the more
+    // specific one first.
+    let mut candidates = identifiers(&["size", "size"]);
+    candidates.push(Item::new("size", Kind::Keyword));
+    candidates.push(Item::new("sizeof", Kind::Keyword));
+    let ranked = rank("size", candidates, &History::default());
+    let kinds: Vec<_> = ranked.iter().map(|i| (i.word.as_str(), i.kind.label())).collect();
+    assert_eq!(kinds, vec![("size", "identifier"), ("size", "keyword"), ("sizeof", "keyword")]);
+
+    let mut history = History::default();
+    assert_eq!(words(&rank("s", identifiers(&["sum", "sz"]), &history)), vec!["sz", "sum"]);
+    history.record("sum");
+    assert_eq!(words(&rank("s", identifiers(&["sum", "sz"]), &history)), vec!["sum", "sz"]);
+}
@@ -0,0 +1,530 @@
+use crate::editor::statusline;
+
+use cursive::theme::{BaseColor, BorderStyle, Color, PaletteColor, Theme};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+pub const PROJECT_CONFIG: &str = ".vimr.toml";
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub tabstop: usize,
+    pub shiftwidth: usize,
+    pub expandtab: bool,
+    pub autopairs: bool,
+    pub matchpairs: String,
+    pub number: bool,
+    pub relativenumber: bool,
+    pub signcolumn: String,
+    pub wrap: bool,
+    pub linebreak: bool,
+    pub showbreak: String,
+    pub scrolloff: usize,
+    pub sidescrolloff: usize,
+    pub suggest_min_len: usize,
+    /// Most rows the completion menu takes; 0 uses all available space.
+    pub pumheight: usize,
+    pub double_tab_ms: u64,
+    pub theme: String,
+    pub statusline: String,
+    pub leader: String,
+    pub timeoutlen: u64,
+    /// Name for the `$USER` snippet variable; the login name if empty.
+    pub author: String,
+    /// Language server started for C and C++ files; none if empty.
+    pub lsp_command: String,
+    /// Command `:make` runs; `%` is the file name, `%<` the name without
+    /// extension.
+    pub makeprg: String,
+    /// Command `:Run` starts the built program with, expanded like
+    /// `makeprg`.
+    pub run_command: String,
+    /// Milliseconds of CPU time a test may use before it is TLE.
+    pub time_limit: u64,
+    /// Milliseconds a test may take on the clock, waiting included, before
+    /// it is killed; 0 for twice `time_limit`.
+    pub wall_time_limit: u64,
+    /// Megabytes of memory a test may use; 0 for no limit.
+    pub memory_limit: u64,
+    /// Megabytes a test may write; 0 for no limit.
+    pub output_limit: u64,
+    /// How `:Run` compares output with the answer: `exact`, `tokens` or
+    /// `float`.
+    pub compare: String,
+    /// Error allowed between numbers when `compare` is `float`.
+    pub float_epsilon: f64,
+    /// Most tests `:Stress` tries.
+    pub stress_iterations: usize,
+    /// Seconds `:Stress` goes on for at most.
+    pub stress_seconds: u64,
+    pub keymaps: HashMap<String, HashMap<String, String>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            tabstop: 4,
+            shiftwidth: 4,
+            expandtab: true,
+            autopairs: true,
+            matchpairs: "(:),{:},[:]".to_string(),
+            number: false,
+            relativenumber: false,
+            signcolumn: "auto".to_string(),
+            wrap: false,
+            linebreak: false,
+            showbreak: String::new(),
+            scrolloff: 0,
+            sidescrolloff: 0,
+            suggest_min_len: 2,
+            pumheight: 10,
+            double_tab_ms: 300,
+            theme: "dark".to_string(),
+            statusline: statusline::DEFAULT_FORMAT.to_string(),
+            leader: "\\".to_string(),
+            timeoutlen: 1000,
+            author: String::new(),
+            lsp_command: "clangd".to_string(),
+            makeprg: "g++ -std=c++17 -O2 -Wall % -o %<".to_string(),
+            run_command: "./%<".to_string(),
+            time_limit: 2000,
+            wall_time_limit: 0,
+            memory_limit: 256,
+            output_limit: 64,
+            compare: "tokens".to_string(),
+            float_epsilon: 1e-6,
+            stress_iterations: 1000,
+            stress_seconds: 60,
+            keymaps: HashMap::new(),
+        }
+    }
+}
+
+// Option names accepted by `:set`, with their short vim-style aliases.
+const OPTIONS: &[(&str, &str)] = &[
+    ("tabstop", "ts"),
+    ("shiftwidth", "sw"),
+    ("expandtab", "et"),
+    ("autopairs", "ap"),
+    ("matchpairs", "mps"),
+    ("number", "nu"),
+    ("relativenumber", "rnu"),
+    ("signcolumn", "scl"),
+    ("wrap", "wrap"),
+    ("linebreak", "lbr"),
+    ("showbreak", "sbr"),
+    ("scrolloff", "so"),
+    ("sidescrolloff", "siso"),
+    ("suggest_min_len", "sml"),
+    ("pumheight", "ph"),
+    ("double_tab_ms", "dtm"),
+    ("theme", "theme"),
+    ("statusline", "stl"),
+    ("leader", "leader"),
+    ("timeoutlen", "tm"),
+    ("author", "author"),
+    ("lsp_command", "lsp"),
+    ("makeprg", "mp"),
+    ("run_command", "rc"),
+    ("time_limit", "tl"),
+    ("wall_time_limit", "wtl"),
+    ("memory_limit", "ml"),
+    ("output_limit", "ol"),
+    ("compare", "cmp"),
+    ("float_epsilon", "eps"),
+    ("stress_iterations", "si"),
+    ("stress_seconds", "ss"),
+];
+
+/// Directory holding the user configuration, `$XDG_CONFIG_HOME/vimr` or
+/// `~/.config/vimr`.
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("vimr"))
+}
+
+fn merge(base: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn read_table(path: PathBuf) -> io::Result<toml::Table> {
+    match fs::read_to_string(&path) {
+        Ok(text) => text.parse::<toml::Table>().map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(toml::Table::new()),
+        Err(e) => Err(e),
+    }
+}
+
+impl Config {
+    /// Loads `config.toml` from the user config dir, then applies the
+    /// project's `.vimr.toml` from the working directory on top of it.
+    pub fn load() -> io::Result<Config> {
+        let mut table = match config_dir() {
+            Some(dir) => read_table(dir.join("config.toml"))?,
+            None => toml::Table::new(),
+        };
+        merge(&mut table, read_table(PathBuf::from(PROJECT_CONFIG))?);
+
+        let config: Config = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        config.validate().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.tabstop == 0 || self.shiftwidth == 0 {
+            return Err("tabstop and shiftwidth must be positive".to_string());
+        }
+        if builtin_theme(&self.theme).is_none() {
+            return Err(format!("unknown theme: {}", self.theme));
+        }
+        if !matches!(self.signcolumn.as_str(), "auto" | "yes" | "no") {
+            return Err(format!("invalid signcolumn: {}", self.signcolumn));
+        }
+        if !matches!(self.compare.as_str(), "exact" | "tokens" | "float") {
+            return Err(format!("invalid compare: {}", self.compare));
+        }
+        if self.pairs().is_none() {
+            return Err(format!("invalid matchpairs: {}", self.matchpairs));
+        }
+        Ok(())
+    }
+
+    /// The text inserted for one level of indentation.
+    pub fn indent_unit(&self) -> String {
+        if self.expandtab {
+            " ".repeat(self.shiftwidth)
+        } else {
+            let mut unit = "\t".repeat(self.shiftwidth / self.tabstop);
+            unit.push_str(&" ".repeat(self.shiftwidth % self.tabstop));
+            unit
+        }
+    }
+
+    /// The closing character auto-inserted after `open`, if autopairs is on.
+    pub fn closing_pair(&self, open: char) -> Option<char> {
+        if !self.autopairs {
+            return None;
+        }
+        self.pairs()?.into_iter().find(|&(o, _)| o == open).map(|(_, c)| c)
+    }
+
+    fn pairs(&self) -> Option<Vec<(char, char)>> {
+        self.matchpairs
+            .split(',')
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                let chars: Vec<char> = p.chars().collect();
+                match chars[..] {
+                    [open, ':', close] => Some((open, close)),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    pub fn theme(&self) -> Theme {
+        builtin_theme(&self.theme).unwrap_or_else(dark_theme)
+    }
+
+    fn canonical(name: &str) -> Option<&'static str> {
+        OPTIONS
+            .iter()
+            .find(|(full, short)| *full == name || *short == name)
+            .map(|(full, _)| *full)
+    }
+
+    fn is_bool(name: &str) -> bool {
+        matches!(name, "expandtab" | "autopairs" | "number" | "relativenumber" | "wrap" | "linebreak")
+    }
+
+    /// Formats an option as `name=value`, or `name`/`noname` for booleans.
+    pub fn get(&self, name: &str) -> Option<String> {
+        let name = Self::canonical(name)?;
+        let value = match name {
+            "tabstop" => self.tabstop.to_string(),
+            "shiftwidth" => self.shiftwidth.to_string(),
+            "matchpairs" => self.matchpairs.clone(),
+            "showbreak" => self.showbreak.clone(),
+            "scrolloff" => self.scrolloff.to_string(),
+            "sidescrolloff" => self.sidescrolloff.to_string(),
+            "suggest_min_len" => self.suggest_min_len.to_string(),
+            "pumheight" => self.pumheight.to_string(),
+            "double_tab_ms" => self.double_tab_ms.to_string(),
+            "theme" => self.theme.clone(),
+            "statusline" => self.statusline.clone(),
+            "signcolumn" => self.signcolumn.clone(),
+            "leader" => self.leader.clone(),
+            "timeoutlen" => self.timeoutlen.to_string(),
+            "author" => self.author.clone(),
+            "lsp_command" => self.lsp_command.clone(),
+            "makeprg" => self.makeprg.clone(),
+            "run_command" => self.run_command.clone(),
+            "time_limit" => self.time_limit.to_string(),
+            "wall_time_limit" => self.wall_time_limit.to_string(),
+            "memory_limit" => self.memory_limit.to_string(),
+            "output_limit" => self.output_limit.to_string(),
+            "compare" => self.compare.clone(),
+            "float_epsilon" => self.float_epsilon.to_string(),
+            "stress_iterations" => self.stress_iterations.to_string(),
+            "stress_seconds" => self.stress_seconds.to_string(),
+            "expandtab" => return Some(Self::flag(name, self.expandtab)),
+            "autopairs" => return Some(Self::flag(name, self.autopairs)),
+            "number" => return Some(Self::flag(name, self.number)),
+            "relativenumber" => return Some(Self::flag(name, self.relativenumber)),
+            "wrap" => return Some(Self::flag(name, self.wrap)),
+            "linebreak" => return Some(Self::flag(name, self.linebreak)),
+            _ => return None,
+        };
+        Some(format!("{}={}", name, value))
+    }
+
+    fn flag(name: &str, on: bool) -> String {
+        format!("{}{}", if on { "" } else { "no" }, name)
+    }
+
+    fn bool_option(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "expandtab" => Some(&mut self.expandtab),
+            "autopairs" => Some(&mut self.autopairs),
+            "number" => Some(&mut self.number),
+            "relativenumber" => Some(&mut self.relativenumber),
+            "wrap" => Some(&mut self.wrap),
+            "linebreak" => Some(&mut self.linebreak),
+            _ => None,
+        }
+    }
+
+    /// Applies the arguments of a `:set` command. Returns the text to echo
+    /// back for queries such as `:set tabstop?`.
+    pub fn set(&mut self, args: &str) -> Result<Option<String>, String> {
+        if args.trim().is_empty() {
+            let all: Vec<String> = OPTIONS.iter().filter_map(|(name, _)| self.get(name)).collect();
+            return Ok(Some(all.join("  ")));
+        }
+
+        let mut shown = Vec::new();
+        for arg in split_args(args) {
+            if let Some(text) = self.set_one(&arg)? {
+                shown.push(text);
+            }
+        }
+        Ok(if shown.is_empty() { None } else { Some(shown.join("  ")) })
+    }
+
+    fn set_one(&mut self, arg: &str) -> Result<Option<String>, String> {
+        let unknown = || format!("E518: Unknown option: {}", arg);
+        let invalid = || format!("E474: Invalid argument: {}", arg);
+
+        if let Some(name) = arg.strip_suffix('?') {
+            return self.get(name).map(Some).ok_or_else(unknown);
+        }
+
+        if let Some((name, value)) = arg.split_once('=') {
+            let name = Self::canonical(name).ok_or_else(unknown)?;
+            let mut updated = self.clone();
+            updated.assign(name, value)?;
+            updated.validate().map_err(|e| format!("E474: {}", e))?;
+            *self = updated;
+            return Ok(None);
+        }
+
+        let (name, value) = if let Some(name) = arg.strip_suffix('!') {
+            (name, None)
+        } else if let Some(name) = arg.strip_prefix("inv") {
+            (name, None)
+        } else if let Some(name) = arg.strip_prefix("no").filter(|n| Self::canonical(n).is_some()) {
+            (name, Some(false))
+        } else {
+            // `:set tabstop` shows the value, like vim.
+            let name = Self::canonical(arg).ok_or_else(unknown)?;
+            if !Self::is_bool(name) {
+                return Ok(self.get(name));
+            }
+            (name, Some(true))
+        };
+
+        let name = Self::canonical(name).ok_or_else(unknown)?;
+        let option = self.bool_option(name).ok_or_else(invalid)?;
+        *option = value.unwrap_or(!*option);
+        Ok(None)
+    }
+
+    fn assign(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let number = || {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("E521: Number required after =: {}={}", name, value))
+        };
+        match name {
+            "tabstop" => self.tabstop = number()?,
+            "shiftwidth" => self.shiftwidth = number()?,
+            "scrolloff" => self.scrolloff = number()?,
+            "sidescrolloff" => self.sidescrolloff = number()?,
+            "showbreak" => self.showbreak = value.to_string(),
+            "suggest_min_len" => self.suggest_min_len = number()?,
+            "pumheight" => self.pumheight = number()?,
+            "double_tab_ms" => self.double_tab_ms = number()? as u64,
+            "matchpairs" => self.matchpairs = value.to_string(),
+            "theme" => self.theme = value.to_string(),
+            "statusline" => self.statusline = value.to_string(),
+            "signcolumn" => self.signcolumn = value.to_string(),
+            "leader" => self.leader = value.to_string(),
+            "timeoutlen" => self.timeoutlen = number()? as u64,
+            "author" => self.author = value.to_string(),
+            "lsp_command" => self.lsp_command = value.to_string(),
+            "makeprg" => self.makeprg = value.to_string(),
+            "run_command" => self.run_command = value.to_string(),
+            "time_limit" => self.time_limit = number()? as u64,
+            "wall_time_limit" => self.wall_time_limit = number()? as u64,
+            "memory_limit" => self.memory_limit = number()? as u64,
+            "output_limit" => self.output_limit = number()? as u64,
+            "compare" => self.compare = value.to_string(),
+            "stress_iterations" => self.stress_iterations = number()?,
+            "stress_seconds" => self.stress_seconds = number()? as u64,
+            "float_epsilon" => {
+                self.float_epsilon =
+                    value.parse::<f64>().map_err(|_| format!("E521: Number required after =: {}={}", name, value))?
+            }
+            _ => return Err(format!("E474: Invalid argument: {}={}", name, value)),
+        }
+        Ok(())
+    }
+}
+
+fn builtin_theme(name: &str) -> Option<Theme> {
+    match name {
+        "dark" => Some(dark_theme()),
+        "light" => Some(light_theme()),
+        _ => None,
+    }
+}
+
+fn dark_theme() -> Theme {
+    let mut theme = Theme {
+        shadow: false,
+        borders: BorderStyle::Simple,
+        ..Theme::default()
+    };
+    theme.palette[PaletteColor::Background] = Color::Dark(BaseColor::Black);
+    theme.palette[PaletteColor::View] = Color::Dark(BaseColor::Black);
+    theme.palette[PaletteColor::Primary] = Color::Light(BaseColor::White);
+    theme.palette[PaletteColor::Secondary] = Color::Light(BaseColor::Blue);
+    theme.palette[PaletteColor::Tertiary] = Color::Light(BaseColor::Green);
+    theme.palette[PaletteColor::TitlePrimary] = Color::Light(BaseColor::Green);
+    theme.palette[PaletteColor::TitleSecondary] = Color::Light(BaseColor::Blue);
+    theme
+}
+
+fn light_theme() -> Theme {
+    let mut theme = Theme {
+        shadow: false,
+        borders: BorderStyle::Simple,
+        ..Theme::default()
+    };
+    theme.palette[PaletteColor::Background] = Color::Light(BaseColor::White);
+    theme.palette[PaletteColor::View] = Color::Light(BaseColor::White);
+    theme.palette[PaletteColor::Primary] = Color::Dark(BaseColor::Black);
+    theme.palette[PaletteColor::Secondary] = Color::Dark(BaseColor::Blue);
+    theme.palette[PaletteColor::Tertiary] = Color::Dark(BaseColor::Green);
+    theme.palette[PaletteColor::TitlePrimary] = Color::Dark(BaseColor::Green);
+    theme.palette[PaletteColor::TitleSecondary] = Color::Dark(BaseColor::Blue);
+    theme
+}
+
+/// Splits `:set` arguments at whitespace. As in vim, `\ ` is a space
+/// inside a value and `\\` a backslash; other backslashes are kept.
+fn split_args(args: &str) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut arg = String::new();
+    let mut chars = args.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek().is_some_and(|&next| next == '\\' || next.is_whitespace()) => arg.extend(chars.next()),
+            c if c.is_whitespace() => {
+                if !arg.is_empty() {
+                    split.push(std::mem::take(&mut arg));
+                }
+            }
+            c => arg.push(c),
+        }
+    }
+    if !arg.is_empty() {
+        split.push(arg);
+    }
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+
+    #[test]
+    fn set_switches_booleans() {
+        let mut config = Config::default();
+        assert_eq!(config.set("nu"), Ok(None));
+        assert!(config.number);
+        assert_eq!(config.set("nonumber"), Ok(None));
+        assert!(!config.number);
+        assert_eq!(config.set("invnu"), Ok(None));
+        assert!(config.number);
+        assert_eq!(config.set("number!"), Ok(None));
+        assert!(!config.number);
+        assert_eq!(config.set("noet wrap"), Ok(None));
+        assert!(!config.expandtab && config.wrap);
+    }
+
+    #[test]
+    fn set_shows_values() {
+        let mut config = Config::default();
+        assert_eq!(config.set("ts?"), Ok(Some("tabstop=4".to_string())));
+        assert_eq!(config.set("tabstop"), Ok(Some("tabstop=4".to_string())));
+        assert_eq!(config.set("et? nu?"), Ok(Some("expandtab  nonumber".to_string())));
+        assert!(config.set("").is_ok_and(|all| all.is_some_and(|all| all.contains("shiftwidth=4"))));
+    }
+
+    #[test]
+    fn set_assigns_values() {
+        let mut config = Config::default();
+        assert_eq!(config.set("ts=8 sw=2"), Ok(None));
+        assert_eq!((config.tabstop, config.shiftwidth), (8, 2));
+        assert_eq!(config.set("eps=0.5"), Ok(None));
+        assert_eq!(config.float_epsilon, 0.5);
+        assert_eq!(config.set("stl=%f\\ %m\\ \\\\%=%l ts=2"), Ok(None));
+        assert_eq!((config.statusline.as_str(), config.tabstop), ("%f %m \\%=%l", 2));
+        assert_eq!(config.set("stl=a\\b"), Ok(None));
+        assert_eq!(config.statusline, "a\\b");
+    }
+
+    #[test]
+    fn set_rejects_bad_arguments() {
+        let mut config = Config::default();
+        assert_eq!(config.set("nots"), Err("E474: Invalid argument: nots".to_string()));
+        assert_eq!(config.set("invts"), Err("E474: Invalid argument: invts".to_string()));
+        assert_eq!(config.set("ts!"), Err("E474: Invalid argument: ts!".to_string()));
+        assert_eq!(config.set("nosuch"), Err("E518: Unknown option: nosuch".to_string()));
+        assert_eq!(config.set("nosuch?"), Err("E518: Unknown option: nosuch?".to_string()));
+        assert_eq!(config.set("ts=x"), Err("E521: Number required after =: tabstop=x".to_string()));
+        assert!(config.set("ts=0").is_err_and(|e| e.starts_with("E474: ")));
+        assert!(config.set("theme=neon").is_err_and(|e| e.starts_with("E474: ")));
+        assert_eq!(config.tabstop, 4);
+    }
+}
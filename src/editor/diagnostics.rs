@@ -0,0 +1,137 @@
+//! Errors and warnings reported for the buffer, by the compiler or a
+//! language server, with the columns they point at.
+
+use crate::editor::gutter::SignKind;
+use crate::editor::lsp::protocol;
+use cursive::theme::{BaseColor, Color};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Information => "info",
+            Severity::Hint => "hint",
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            Severity::Error => Color::Light(BaseColor::Red),
+            Severity::Warning => Color::Light(BaseColor::Yellow),
+            Severity::Information => Color::Light(BaseColor::Blue),
+            Severity::Hint => Color::Light(BaseColor::Cyan),
+        }
+    }
+
+    pub fn sign(self) -> SignKind {
+        match self {
+            Severity::Error => SignKind::Error,
+            Severity::Warning => SignKind::Warning,
+            Severity::Information | Severity::Hint => SignKind::Info,
+        }
+    }
+}
+
+/// The `source` of diagnostics from `:make`.
+pub const COMPILER: &str = "compiler";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub line: usize,
+    /// Byte columns of the text it points at on `line`; `end` may equal
+    /// `start` when only a position is known.
+    pub start: usize,
+    pub end: usize,
+    pub severity: Severity,
+    pub message: String,
+    pub source: Option<String>,
+}
+
+impl Diagnostic {
+    /// A language server diagnostic placed in `lines`. Ranges running past
+    /// their first line stop at its end.
+    pub fn from_lsp(diagnostic: &protocol::Diagnostic, lines: &[String]) -> Diagnostic {
+        let range = diagnostic.range;
+        let text = lines.get(range.start.line).map_or("", |l| l.as_str());
+        let start = range.start.byte_in(text);
+        let end = if range.end.line == range.start.line { range.end.byte_in(text) } else { text.len() };
+        Diagnostic {
+            line: range.start.line,
+            start,
+            end: end.max(start),
+            severity: diagnostic.severity,
+            message: diagnostic.message.clone(),
+            source: diagnostic.source.clone(),
+        }
+    }
+
+    /// The byte range to underline in `text`: at least one character, so
+    /// a bare position still shows.
+    pub fn span(&self, text: &str) -> (usize, usize) {
+        let start = self.start.min(text.len());
+        let end = if self.end > start {
+            self.end.min(text.len())
+        } else {
+            text[start..].chars().next().map_or(start, |c| start + c.len_utf8())
+        };
+        (start, end)
+    }
+
+    pub fn is_from_compiler(&self) -> bool {
+        self.source.as_deref() == Some(COMPILER)
+    }
+
+    /// One line for the `:Diagnostics` panel and messages.
+    pub fn describe(&self) -> String {
+        let source = self.source.as_ref().map_or(String::new(), |s| format!(" [{}]", s));
+        format!("{}: {}{}", self.severity.label(), self.message.lines().next().unwrap_or(""), source)
+    }
+}
+
+/// Sorts by position, the most severe first on the same spot.
+pub fn sort(diagnostics: &mut [Diagnostic]) {
+    diagnostics.sort_by_key(|d| (d.line, d.start, d.severity));
+}
+
+/// The diagnostic to show for the cursor at `(x, y)`: the most severe one
+/// under it, or else on its line.
+pub fn at(diagnostics: &[Diagnostic], (x, y): (usize, usize)) -> Option<&Diagnostic> {
+    let on_line = diagnostics.iter().filter(|d| d.line == y);
+    let under = on_line.clone().filter(|d| (d.start..d.end.max(d.start + 1)).contains(&x)).min_by_key(|d| d.severity);
+    under.or_else(|| on_line.min_by_key(|d| d.severity))
+}
+
+/// Index of the diagnostic after (or before, unless `forward`) the cursor
+/// at `(x, y)` in the sorted `diagnostics`, wrapping around the file.
+pub fn next(diagnostics: &[Diagnostic], (x, y): (usize, usize), forward: bool) -> Option<usize> {
+    if diagnostics.is_empty() {
+        return None;
+    }
+    let found = if forward {
+        diagnostics.iter().position(|d| (d.line, d.start) > (y, x))
+    } else {
+        diagnostics.iter().rposition(|d| (d.line, d.start) < (y, x))
+    };
+    Some(found.unwrap_or(if forward { 0 } else { diagnostics.len() - 1 }))
+}
+
+/// Keeps diagnostics on their lines after `delta` lines were inserted (or
+/// removed, if negative) at `at`. Those on removed lines go.
+pub fn shift(diagnostics: &mut Vec<Diagnostic>, at: usize, delta: isize) {
+    if delta < 0 {
+        let removed = at..at + delta.unsigned_abs();
+        diagnostics.retain(|d| !removed.contains(&d.line));
+    }
+    for diagnostic in diagnostics.iter_mut().filter(|d| d.line >= at) {
+        diagnostic.line = diagnostic.line.saturating_add_signed(delta);
+    }
+}
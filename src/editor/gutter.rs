@@ -0,0 +1,260 @@
+use cursive::theme::{BaseColor, Color};
+use std::path::Path;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
+
+/// Width of the sign column, in cells.
+pub const SIGN_WIDTH: usize = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SignKind {
+    Error,
+    Warning,
+    Info,
+    GitAdded,
+    GitChanged,
+    GitRemoved,
+}
+
+impl SignKind {
+    pub fn text(&self) -> String {
+        match self {
+            SignKind::Error => "E ".to_string(),
+            SignKind::Warning => "W ".to_string(),
+            SignKind::Info => "I ".to_string(),
+            SignKind::GitAdded => "+ ".to_string(),
+            SignKind::GitChanged => "~ ".to_string(),
+            SignKind::GitRemoved => "_ ".to_string(),
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            SignKind::GitRemoved | SignKind::Error => Color::Light(BaseColor::Red),
+            SignKind::GitAdded => Color::Light(BaseColor::Green),
+            SignKind::GitChanged | SignKind::Warning => Color::Light(BaseColor::Yellow),
+            SignKind::Info => Color::Light(BaseColor::Blue),
+        }
+    }
+
+    // When several signs share a line only the highest priority one is shown.
+    pub fn priority(&self) -> u8 {
+        match self {
+            SignKind::Error => 3,
+            SignKind::Warning => 2,
+            SignKind::Info | SignKind::GitAdded | SignKind::GitChanged | SignKind::GitRemoved => 1,
+        }
+    }
+
+    pub fn is_diagnostic(&self) -> bool {
+        matches!(self, SignKind::Error | SignKind::Warning | SignKind::Info)
+    }
+
+    pub fn is_git(&self) -> bool {
+        matches!(self, SignKind::GitAdded | SignKind::GitChanged | SignKind::GitRemoved)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Sign {
+    pub line: usize,
+    pub kind: SignKind,
+}
+
+/// Signs placed on buffer lines, kept in step with line insertions and
+/// deletions through `shift`.
+#[derive(Default)]
+pub struct Signs {
+    signs: Vec<Sign>,
+}
+
+impl Signs {
+    pub fn place(&mut self, line: usize, kind: SignKind) {
+        if !self.signs.iter().any(|s| s.line == line && s.kind == kind) {
+            self.signs.push(Sign { line, kind });
+        }
+    }
+
+    pub fn clear(&mut self, matches: impl Fn(&SignKind) -> bool) {
+        self.signs.retain(|s| !matches(&s.kind));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signs.is_empty()
+    }
+
+    /// The sign to display on `line`, if any.
+    pub fn at(&self, line: usize) -> Option<SignKind> {
+        self.signs
+            .iter()
+            .filter(|s| s.line == line)
+            .max_by_key(|s| s.kind.priority())
+            .map(|s| s.kind)
+    }
+
+    /// Moves signs after an edit that inserted (`delta > 0`) or removed
+    /// (`delta < 0`) lines at `at`. Signs on removed lines are dropped.
+    pub fn shift(&mut self, at: usize, delta: isize) {
+        if delta < 0 {
+            let removed = at..at + delta.unsigned_abs();
+            self.signs.retain(|s| !removed.contains(&s.line));
+        }
+        for sign in self.signs.iter_mut().filter(|s| s.line >= at) {
+            sign.line = sign.line.saturating_add_signed(delta);
+        }
+    }
+}
+
+/// Width of the line number column for a buffer of `line_count` lines,
+/// including the separating space.
+pub fn number_width(line_count: usize) -> usize {
+    line_count.max(1).to_string().len().max(3) + 1
+}
+
+/// Formats the number shown for `line` given the cursor line and the
+/// `number`/`relativenumber` options. With both set the cursor line shows
+/// its absolute number, left aligned, as in vim.
+pub fn format_number(line: usize, cursor_line: usize, number: bool, relative: bool, width: usize) -> String {
+    let digits = width - 1;
+    if relative && line != cursor_line {
+        format!("{:>digits$} ", line.abs_diff(cursor_line))
+    } else if relative && number {
+        format!("{:<digits$} ", line + 1)
+    } else if relative {
+        format!("{:>digits$} ", 0)
+    } else {
+        format!("{:>digits$} ", line + 1)
+    }
+}
+
+/// Signs for lines that differ from `HEAD`, from `git diff -U0`. Returns
+/// nothing when the file is not tracked or git is unavailable.
+pub fn git_changes(path: &str) -> Vec<Sign> {
+    let path = Path::new(path);
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let Some(name) = path.file_name() else {
+        return Vec::new();
+    };
+    let output = match Command::new("git")
+        .args(["diff", "--no-color", "--no-ext-diff", "-U0", "HEAD", "--"])
+        .arg(name)
+        .current_dir(dir)
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        _ => return Vec::new(),
+    };
+    parse_diff(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Signs for the hunks of a `git diff -U0`.
+fn parse_diff(diff: &str) -> Vec<Sign> {
+    let mut signs = Vec::new();
+    for line in diff.lines() {
+        let Some(hunk) = line.strip_prefix("@@ -") else {
+            continue;
+        };
+        let mut ranges = hunk.split_whitespace();
+        let (Some(old), Some(new)) = (ranges.next(), ranges.next()) else {
+            continue;
+        };
+        let (_, old_count) = parse_range(old);
+        let (start, new_count) = parse_range(new.trim_start_matches('+'));
+
+        if new_count == 0 {
+            // Deleted lines are marked on the line above the deletion.
+            signs.push(Sign { line: start.saturating_sub(1), kind: SignKind::GitRemoved });
+            continue;
+        }
+        let kind = if old_count == 0 { SignKind::GitAdded } else { SignKind::GitChanged };
+        for line in start..start + new_count {
+            signs.push(Sign { line: line - 1, kind });
+        }
+    }
+    signs
+}
+
+/// `git_changes` for a file, run in the background so a slow repository
+/// doesn't hold up loading or saving.
+pub struct GitJob {
+    pub path: String,
+    // In a mutex only so the editor view holding the job is `Sync`.
+    finished: Mutex<Receiver<Vec<Sign>>>,
+}
+
+impl GitJob {
+    pub fn start(path: &str) -> GitJob {
+        let (sender, finished) = mpsc::channel();
+        let diff_path = path.to_string();
+        thread::spawn(move || {
+            sender.send(git_changes(&diff_path)).ok();
+        });
+        GitJob { path: path.to_string(), finished: Mutex::new(finished) }
+    }
+
+    /// The signs, once git has finished.
+    pub fn poll(&mut self) -> Option<Vec<Sign>> {
+        self.finished.get_mut().ok()?.try_recv().ok()
+    }
+}
+
+fn parse_range(range: &str) -> (usize, usize) {
+    let (start, count) = range.split_once(',').unwrap_or((range, "1"));
+    (start.parse().unwrap_or(0), count.parse().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_numbers() {
+        // Line 5 (index 4) with the cursor on index 7, four columns wide.
+        assert_eq!(format_number(4, 7, true, false, 4), "  5 ");
+        assert_eq!(format_number(4, 7, false, true, 4), "  3 ");
+        assert_eq!(format_number(4, 7, true, true, 4), "  3 ");
+        // The cursor line: absolute, left aligned with both set.
+        assert_eq!(format_number(7, 7, true, false, 4), "  8 ");
+        assert_eq!(format_number(7, 7, false, true, 4), "  0 ");
+        assert_eq!(format_number(7, 7, true, true, 4), "8   ");
+        assert_eq!(format_number(1233, 0, true, false, number_width(1234)), "1234 ");
+    }
+
+    #[test]
+    fn pads_number_width() {
+        assert_eq!(number_width(0), 4);
+        assert_eq!(number_width(999), 4);
+        assert_eq!(number_width(1000), 5);
+        assert_eq!(number_width(123456), 7);
+    }
+
+    #[test]
+    fn parses_hunk_headers() {
+        assert_eq!(parse_range("3"), (3, 1));
+        assert_eq!(parse_range("3,4"), (3, 4));
+        assert_eq!(parse_range("3,0"), (3, 0));
+
+        let diff = "diff --git a/a.cpp b/a.cpp
+index 1111111..2222222 100644
+--- a/a.cpp
++++ b/a.cpp
+@@ -3 +3 @@ int main() {
+-    int a;
++    long a;
+@@ -5,0 +6,2 @@
++    a++;
++    a--;
+@@ -10,2 +11,0 @@ int main() {
+-    return 0;
+-}
+@@ -1,0 +1 @@
++// header
+";
+        // Deletions are marked on the line above: new line 11, index 10.
+        let signs: Vec<(usize, String)> = parse_diff(diff).iter().map(|s| (s.line, s.kind.text())).collect();
+        let expected = [(2, "~ "), (5, "+ "), (6, "+ "), (10, "_ "), (0, "+ ")];
+        assert_eq!(signs, expected.map(|(line, text)| (line, text.to_string())));
+    }
+}
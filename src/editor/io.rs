@@ -2,15 +2,38 @@ use std::fs;
 use std::io::{self, Write};
 use std::path::Path;
 
-pub fn load_file(path: &str) -> io::Result<Vec<String>> {
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    Unix,
+    Dos,
+}
+
+impl FileFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            FileFormat::Unix => "unix",
+            FileFormat::Dos => "dos",
+        }
+    }
+
+    fn line_ending(self) -> &'static str {
+        match self {
+            FileFormat::Unix => "\n",
+            FileFormat::Dos => "\r\n",
+        }
+    }
+}
+
+pub fn load_file(path: impl AsRef<Path>) -> io::Result<(Vec<String>, FileFormat)> {
     let contents = fs::read_to_string(path)?;
-    Ok(contents.lines().map(String::from).collect())
+    let format = if contents.contains("\r\n") { FileFormat::Dos } else { FileFormat::Unix };
+    Ok((contents.lines().map(String::from).collect(), format))
 }
 
-pub fn save_file(path: &str, content: &[String]) -> io::Result<()> {
+pub fn save_file(path: impl AsRef<Path>, content: &[String], format: FileFormat) -> io::Result<()> {
     let mut file = fs::File::create(path)?;
     for line in content {
-        writeln!(file, "{}", line)?;
+        write!(file, "{}{}", line, format.line_ending())?;
     }
     Ok(())
 }
@@ -0,0 +1,482 @@
+use crate::editor::modes::EditorMode;
+
+use cursive::event::{Event, Key};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+// Upper bound on expansions per drained input, so `:nmap a a` cannot hang.
+const MAX_EXPANSIONS: usize = 1000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MapMode {
+    Normal,
+    Insert,
+    Visual,
+    Command,
+}
+
+impl MapMode {
+    fn prefix(self) -> char {
+        match self {
+            MapMode::Normal => 'n',
+            MapMode::Insert => 'i',
+            MapMode::Visual => 'v',
+            MapMode::Command => 'c',
+        }
+    }
+}
+
+impl From<EditorMode> for MapMode {
+    fn from(mode: EditorMode) -> Self {
+        match mode {
+            EditorMode::Normal => MapMode::Normal,
+            EditorMode::Insert => MapMode::Insert,
+            EditorMode::Command => MapMode::Command,
+            EditorMode::Visual | EditorMode::VisualLine => MapMode::Visual,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Mapping {
+    pub lhs: Vec<Event>,
+    pub rhs: Vec<Event>,
+    pub noremap: bool,
+}
+
+/// A parsed `:map`-family command name such as `nnoremap` or `vunmap`.
+pub struct MapCommand {
+    pub modes: Vec<MapMode>,
+    pub noremap: bool,
+    pub unmap: bool,
+}
+
+impl MapCommand {
+    pub fn parse(name: &str) -> Option<MapCommand> {
+        let (modes, rest) = match name.chars().next()? {
+            'n' if !name.starts_with("noremap") => (vec![MapMode::Normal], &name[1..]),
+            'i' => (vec![MapMode::Insert], &name[1..]),
+            'v' => (vec![MapMode::Visual], &name[1..]),
+            'c' => (vec![MapMode::Command], &name[1..]),
+            _ => (vec![MapMode::Normal, MapMode::Visual], name),
+        };
+        let (noremap, unmap) = match rest {
+            "map" => (false, false),
+            "noremap" => (true, false),
+            "unmap" => (false, true),
+            _ => return None,
+        };
+        Some(MapCommand { modes, noremap, unmap })
+    }
+}
+
+/// Translates raw key events into the keys the editor acts on, applying
+/// user mappings per mode. Keys wait in `pending` while they are a prefix
+/// of a longer mapping, until the sequence completes or `timeout` passes.
+pub struct Keymap {
+    maps: HashMap<MapMode, Vec<Mapping>>,
+    pub leader: String,
+    pub timeout: Duration,
+    typeahead: VecDeque<(Event, bool)>,
+    pending: Vec<Event>,
+    pending_since: Option<Instant>,
+    expansions: usize,
+    error: Option<String>,
+}
+
+impl Keymap {
+    pub fn new(leader: &str, timeout: Duration) -> Self {
+        Keymap {
+            maps: HashMap::new(),
+            leader: leader.to_string(),
+            timeout,
+            typeahead: VecDeque::new(),
+            pending: Vec::new(),
+            pending_since: None,
+            expansions: 0,
+            error: None,
+        }
+    }
+
+    /// Loads `[keymaps.<section>]` tables from the config. Sections are
+    /// either a mode name (`normal`, `insert`, `visual`, `command`), which
+    /// define non-recursive mappings, or a map command such as `nmap`.
+    pub fn load(&mut self, keymaps: &HashMap<String, HashMap<String, String>>) -> Result<(), String> {
+        for (section, maps) in keymaps {
+            let command = match section.as_str() {
+                "normal" => MapCommand::parse("nnoremap"),
+                "insert" => MapCommand::parse("inoremap"),
+                "visual" => MapCommand::parse("vnoremap"),
+                "command" => MapCommand::parse("cnoremap"),
+                name => MapCommand::parse(name).filter(|c| !c.unmap),
+            }
+            .ok_or_else(|| format!("unknown keymap section: {}", section))?;
+
+            for (lhs, rhs) in maps {
+                for &mode in &command.modes {
+                    self.map(mode, lhs, rhs, command.noremap)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn map(&mut self, mode: MapMode, lhs: &str, rhs: &str, noremap: bool) -> Result<(), String> {
+        let lhs = parse_keys(lhs, &self.leader);
+        let rhs = parse_keys(rhs, &self.leader);
+        if lhs.is_empty() {
+            return Err("E474: Invalid argument".to_string());
+        }
+        let maps = self.maps.entry(mode).or_default();
+        maps.retain(|m| m.lhs != lhs);
+        maps.push(Mapping { lhs, rhs, noremap });
+        Ok(())
+    }
+
+    pub fn unmap(&mut self, mode: MapMode, lhs: &str) -> Result<(), String> {
+        let lhs = parse_keys(lhs, &self.leader);
+        let maps = self.maps.entry(mode).or_default();
+        let before = maps.len();
+        maps.retain(|m| m.lhs != lhs);
+        if maps.len() == before {
+            return Err("E31: No such mapping".to_string());
+        }
+        Ok(())
+    }
+
+    /// Lines describing the mappings of `modes`, as shown by `:map`.
+    pub fn list(&self, modes: &[MapMode]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for mode in modes {
+            let mut maps: Vec<&Mapping> = self.maps.get(mode).into_iter().flatten().collect();
+            maps.sort_by_key(|m| keys_to_string(&m.lhs));
+            for m in maps {
+                lines.push(format!(
+                    "{}  {:<12} {}{}",
+                    mode.prefix(),
+                    keys_to_string(&m.lhs),
+                    if m.noremap { "* " } else { "  " },
+                    keys_to_string(&m.rhs)
+                ));
+            }
+        }
+        lines
+    }
+
+    /// Queues a key typed by the user.
+    pub fn feed(&mut self, event: Event) {
+        self.typeahead.push_back((event, true));
+    }
+
+    /// Inserts keys ahead of any queued input, as when replaying a macro.
+    pub fn insert(&mut self, events: &[Event]) {
+        for event in events.iter().rev() {
+            self.typeahead.push_front((event.clone(), true));
+        }
+    }
+
+    /// Keys typed but still waiting for a longer mapping to complete.
+    pub fn pending(&self) -> &[Event] {
+        &self.pending
+    }
+
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    /// Returns the next key the editor should handle in `mode`, or `None`
+    /// when input is exhausted or waiting on an ambiguous prefix.
+    pub fn next_key(&mut self, mode: MapMode) -> Option<Event> {
+        loop {
+            let Some((event, remap)) = self.typeahead.pop_front() else {
+                self.expansions = 0;
+                let expired = self
+                    .pending_since
+                    .is_some_and(|since| since.elapsed() >= self.timeout);
+                if self.pending.is_empty() || !expired {
+                    return None;
+                }
+                if let Some(event) = self.resolve_pending(mode) {
+                    return Some(event);
+                }
+                continue;
+            };
+
+            if !remap {
+                if self.pending.is_empty() {
+                    return Some(event);
+                }
+                self.typeahead.push_front((event, remap));
+                if let Some(event) = self.resolve_pending(mode) {
+                    return Some(event);
+                }
+                continue;
+            }
+
+            self.pending.push(event);
+            self.pending_since = Some(Instant::now());
+
+            let maps = self.maps.get(&mode).map(Vec::as_slice).unwrap_or_default();
+            let longer = maps
+                .iter()
+                .any(|m| m.lhs.len() > self.pending.len() && m.lhs.starts_with(&self.pending));
+            if longer {
+                continue;
+            }
+            if let Some(event) = self.resolve_pending(mode) {
+                return Some(event);
+            }
+        }
+    }
+
+    // Settles the pending keys without waiting for more input: expands the
+    // longest mapping they start with, or passes the first key through.
+    fn resolve_pending(&mut self, mode: MapMode) -> Option<Event> {
+        let pending = std::mem::take(&mut self.pending);
+        self.pending_since = None;
+
+        let maps = self.maps.get(&mode).map(Vec::as_slice).unwrap_or_default();
+        let mapping = maps
+            .iter()
+            .filter(|m| pending.starts_with(&m.lhs))
+            .max_by_key(|m| m.lhs.len())
+            .cloned();
+
+        let Some(mapping) = mapping else {
+            for event in pending[1..].iter().rev() {
+                self.typeahead.push_front((event.clone(), true));
+            }
+            return pending.into_iter().next();
+        };
+
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            self.typeahead.clear();
+            self.expansions = 0;
+            self.error = Some("E223: recursive mapping".to_string());
+            return None;
+        }
+        for event in pending[mapping.lhs.len()..].iter().rev() {
+            self.typeahead.push_front((event.clone(), true));
+        }
+        // As in vim, a rhs starting with its own lhs does not remap that part.
+        let literal = if mapping.rhs.starts_with(&mapping.lhs) { mapping.lhs.len() } else { 0 };
+        for (i, event) in mapping.rhs.iter().enumerate().rev() {
+            self.typeahead.push_front((event.clone(), !mapping.noremap && i >= literal));
+        }
+        None
+    }
+}
+
+fn named_key(name: &str) -> Option<Event> {
+    let lower = name.to_ascii_lowercase();
+    let event = match lower.as_str() {
+        "esc" => Event::Key(Key::Esc),
+        "cr" | "enter" | "return" => Event::Key(Key::Enter),
+        "tab" => Event::Key(Key::Tab),
+        "s-tab" => Event::Shift(Key::Tab),
+        "bs" | "backspace" => Event::Key(Key::Backspace),
+        "del" => Event::Key(Key::Del),
+        "space" => Event::Char(' '),
+        "lt" => Event::Char('<'),
+        "bar" => Event::Char('|'),
+        "up" => Event::Key(Key::Up),
+        "down" => Event::Key(Key::Down),
+        "left" => Event::Key(Key::Left),
+        "right" => Event::Key(Key::Right),
+        "home" => Event::Key(Key::Home),
+        "end" => Event::Key(Key::End),
+        "pageup" => Event::Key(Key::PageUp),
+        "pagedown" => Event::Key(Key::PageDown),
+        _ => {
+            if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                if (1..=12).contains(&n) {
+                    return Some(Event::Key(Key::from_f(n)));
+                }
+                return None;
+            }
+            let mut chars = name.chars();
+            let (modifier, c) = (chars.next()?, chars.nth(1)?);
+            if chars.next().is_some() || name.chars().nth(1) != Some('-') {
+                return None;
+            }
+            match modifier.to_ascii_lowercase() {
+                'c' => Event::CtrlChar(c.to_ascii_lowercase()),
+                'a' | 'm' => Event::AltChar(c),
+                _ => return None,
+            }
+        }
+    };
+    Some(event)
+}
+
+/// Parses vim key notation (`jk`, `<Esc>`, `<C-n>`, `<Leader>w`) into events.
+/// Unrecognised `<...>` groups are taken literally.
+pub fn parse_keys(notation: &str, leader: &str) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut rest = notation;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                let name = &rest[1..end];
+                if name.eq_ignore_ascii_case("leader") {
+                    events.extend(leader.chars().map(Event::Char));
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+                if let Some(event) = named_key(name) {
+                    events.push(event);
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+        events.push(Event::Char(c));
+        rest = &rest[c.len_utf8()..];
+    }
+    events
+}
+
+/// Formats events back into key notation for display.
+pub fn keys_to_string(events: &[Event]) -> String {
+    events
+        .iter()
+        .map(|event| match event {
+            Event::Char(' ') => "<Space>".to_string(),
+            Event::Char('<') => "<lt>".to_string(),
+            Event::Char(c) => c.to_string(),
+            Event::CtrlChar(c) => format!("<C-{}>", c),
+            Event::AltChar(c) => format!("<A-{}>", c),
+            Event::Shift(Key::Tab) => "<S-Tab>".to_string(),
+            Event::Key(Key::Esc) => "<Esc>".to_string(),
+            Event::Key(Key::Enter) => "<CR>".to_string(),
+            Event::Key(Key::Tab) => "<Tab>".to_string(),
+            Event::Key(Key::Backspace) => "<BS>".to_string(),
+            Event::Key(Key::Del) => "<Del>".to_string(),
+            Event::Key(key) => format!("<{:?}>", key),
+            other => format!("<{:?}>", other),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keymap(timeout: Duration) -> Keymap {
+        Keymap::new(",", timeout)
+    }
+
+    /// Feeds `keys` and collects what comes out in normal mode.
+    fn drain(keymap: &mut Keymap, keys: &str) -> Vec<Event> {
+        for event in parse_keys(keys, ",") {
+            keymap.feed(event);
+        }
+        std::iter::from_fn(|| keymap.next_key(MapMode::Normal)).collect()
+    }
+
+    #[test]
+    fn parses_key_notation() {
+        assert_eq!(
+            parse_keys("a<Esc><C-n><cr><S-Tab><Space><lt><F5><A-x>", ","),
+            vec![
+                Event::Char('a'),
+                Event::Key(Key::Esc),
+                Event::CtrlChar('n'),
+                Event::Key(Key::Enter),
+                Event::Shift(Key::Tab),
+                Event::Char(' '),
+                Event::Char('<'),
+                Event::Key(Key::from_f(5)),
+                Event::AltChar('x'),
+            ]
+        );
+        assert_eq!(parse_keys("<Leader>w", "\\"), vec![Event::Char('\\'), Event::Char('w')]);
+        // Unknown names are typed as they are.
+        assert_eq!(parse_keys("<F13>", ","), "<F13>".chars().map(Event::Char).collect::<Vec<_>>());
+        assert_eq!(keys_to_string(&parse_keys("<c-w>j<Space><lt>", ",")), "<C-w>j<Space><lt>");
+    }
+
+    #[test]
+    fn parses_map_commands() {
+        let command = MapCommand::parse("nnoremap").unwrap();
+        assert!(command.modes == [MapMode::Normal] && command.noremap && !command.unmap);
+        let command = MapCommand::parse("noremap").unwrap();
+        assert!(command.modes == [MapMode::Normal, MapMode::Visual] && command.noremap);
+        assert!(MapCommand::parse("iunmap").is_some_and(|c| c.unmap && c.modes == [MapMode::Insert]));
+        assert!(MapCommand::parse("nmapx").is_none());
+    }
+
+    #[test]
+    fn expands_mappings() {
+        let mut keys = keymap(Duration::from_secs(60));
+        keys.map(MapMode::Normal, "<Leader>w", ":w<CR>", false).unwrap();
+        assert_eq!(drain(&mut keys, ",wx"), parse_keys(":w<CR>x", ","));
+        assert_eq!(drain(&mut keys, "x"), vec![Event::Char('x')]);
+        // Other modes are unaffected.
+        keys.feed(Event::Char(','));
+        assert_eq!(keys.next_key(MapMode::Insert), Some(Event::Char(',')));
+    }
+
+    #[test]
+    fn inserts_replayed_keys_first() {
+        let mut keys = keymap(Duration::from_secs(60));
+        keys.map(MapMode::Normal, "<Leader>w", ":w<CR>", false).unwrap();
+        keys.feed(Event::Char('x'));
+        // Replayed keys are mapped, like typed ones.
+        keys.insert(&parse_keys("a,w", ","));
+        assert_eq!(drain(&mut keys, ""), parse_keys("a:w<CR>x", ","));
+    }
+
+    #[test]
+    fn waits_on_a_prefix_until_timeout() {
+        let mut keys = keymap(Duration::from_secs(60));
+        keys.map(MapMode::Normal, "jk", "<Esc>", false).unwrap();
+        assert_eq!(drain(&mut keys, "j"), vec![]);
+        assert_eq!(keys.pending(), &[Event::Char('j')]);
+        assert_eq!(drain(&mut keys, "k"), vec![Event::Key(Key::Esc)]);
+        assert!(keys.pending().is_empty());
+        // A key that breaks the sequence lets the prefix through.
+        assert_eq!(drain(&mut keys, "jx"), vec![Event::Char('j'), Event::Char('x')]);
+
+        let mut keys = keymap(Duration::ZERO);
+        keys.map(MapMode::Normal, "jk", "<Esc>", false).unwrap();
+        keys.map(MapMode::Normal, "j", "gj", true).unwrap();
+        // Timed out at once: the longest complete mapping wins.
+        assert_eq!(drain(&mut keys, "j"), parse_keys("gj", ","));
+        assert!(keys.pending().is_empty());
+    }
+
+    #[test]
+    fn noremap_and_self_prefixed_rhs_do_not_remap() {
+        let mut keys = keymap(Duration::from_secs(60));
+        keys.map(MapMode::Normal, "a", "b", false).unwrap();
+        keys.map(MapMode::Normal, "b", "c", false).unwrap();
+        keys.map(MapMode::Normal, "x", "b", true).unwrap();
+        keys.map(MapMode::Normal, "y", "yb", false).unwrap();
+        assert_eq!(drain(&mut keys, "a"), vec![Event::Char('c')]);
+        assert_eq!(drain(&mut keys, "x"), vec![Event::Char('b')]);
+        assert_eq!(drain(&mut keys, "y"), vec![Event::Char('y'), Event::Char('c')]);
+    }
+
+    #[test]
+    fn recursive_mappings_stop() {
+        let mut keys = keymap(Duration::from_secs(60));
+        keys.map(MapMode::Normal, "a", "ba", false).unwrap();
+        let out = drain(&mut keys, "a");
+        assert_eq!(out.len(), MAX_EXPANSIONS);
+        assert!(out.iter().all(|e| *e == Event::Char('b')));
+        assert_eq!(keys.take_error(), Some("E223: recursive mapping".to_string()));
+        assert_eq!(drain(&mut keys, "x"), vec![Event::Char('x')]);
+    }
+
+    #[test]
+    fn unmaps_and_lists() {
+        let mut keys = keymap(Duration::from_secs(60));
+        keys.map(MapMode::Normal, "<C-s>", ":w<CR>", true).unwrap();
+        assert_eq!(keys.list(&[MapMode::Normal]), vec!["n  <C-s>        * :w<CR>".to_string()]);
+        assert_eq!(keys.unmap(MapMode::Normal, "<c-s>"), Ok(()));
+        assert_eq!(keys.unmap(MapMode::Normal, "<C-s>"), Err("E31: No such mapping".to_string()));
+        assert_eq!(keys.map(MapMode::Normal, "", "x", false), Err("E474: Invalid argument".to_string()));
+    }
+}
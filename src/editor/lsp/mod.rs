@@ -0,0 +1,379 @@
+//! A Language Server Protocol client: runs a server such as clangd and
+//! talks JSON-RPC to it over its stdin and stdout. Requests return at
+//! once; their results come back from `poll` as `Update`s, which the
+//! editor checks on every refresh.
+
+pub mod protocol;
+#[cfg(test)]
+mod tests;
+
+use protocol::{CompletionItem, Diagnostic, Location, Position, Range, TextEdit};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Mutex;
+use std::thread;
+
+/// What the server sent back, for the editor to act on.
+#[derive(Debug, PartialEq)]
+pub enum Update {
+    Completion(Vec<CompletionItem>),
+    Hover(String),
+    SignatureHelp(String),
+    Definition(Vec<Location>),
+    References(Vec<Location>),
+    /// Edits to make for a rename, per file.
+    Rename(Vec<(PathBuf, Vec<TextEdit>)>),
+    Formatting(PathBuf, Vec<TextEdit>),
+    Diagnostics(PathBuf, Vec<Diagnostic>),
+    /// An error response, or the server going away.
+    Error(String),
+}
+
+// The request a response answers.
+enum Request {
+    Initialize,
+    Completion,
+    Hover,
+    SignatureHelp,
+    Definition,
+    References,
+    Rename,
+    Formatting(PathBuf),
+    Shutdown,
+}
+
+// A file open on the server, as last sent.
+struct Document {
+    version: i64,
+    lines: Vec<String>,
+}
+
+pub struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    // In a mutex only so the editor view holding the client is `Sync`.
+    incoming: Mutex<Receiver<Value>>,
+    next_id: u64,
+    pending: HashMap<u64, Request>,
+    /// Set once the server answers `initialize`; messages wait in `queued`
+    /// until then.
+    ready: bool,
+    queued: Vec<Value>,
+    documents: HashMap<PathBuf, Document>,
+    /// Characters after which the server offers completion, from its
+    /// capabilities.
+    pub trigger_characters: Vec<String>,
+    exited: bool,
+}
+
+impl Client {
+    /// Starts `command` (a program and its arguments, split at spaces) as
+    /// the language server for the project in `root`.
+    pub fn start(command: &str, root: &Path) -> io::Result<Client> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no command"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().ok_or_else(|| io::Error::other("no stdin"))?;
+        let stdout = child.stdout.take().ok_or_else(|| io::Error::other("no stdout"))?;
+
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Ok(Some(message)) = protocol::read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut client = Client {
+            child,
+            stdin,
+            incoming: Mutex::new(incoming),
+            next_id: 1,
+            pending: HashMap::new(),
+            ready: false,
+            queued: Vec::new(),
+            documents: HashMap::new(),
+            trigger_characters: Vec::new(),
+            exited: false,
+        };
+        let params = json!({
+            "processId": std::process::id(),
+            "rootUri": protocol::path_to_uri(root),
+            "capabilities": {
+                "textDocument": {
+                    "synchronization": { "didSave": true },
+                    "completion": { "completionItem": { "snippetSupport": false } },
+                    "hover": { "contentFormat": ["plaintext", "markdown"] },
+                    "signatureHelp": {},
+                    "definition": {},
+                    "references": {},
+                    "rename": {},
+                    "formatting": {},
+                    "publishDiagnostics": {},
+                },
+            },
+        });
+        let id = client.next_id();
+        client.pending.insert(id, Request::Initialize);
+        client.write(&json!({ "jsonrpc": "2.0", "id": id, "method": "initialize", "params": params }))?;
+        Ok(client)
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    fn write(&mut self, message: &Value) -> io::Result<()> {
+        let result = protocol::write_message(&mut self.stdin, message);
+        if result.is_err() {
+            self.exited = true;
+        }
+        result
+    }
+
+    // Sends `message`, or keeps it until the server is initialized.
+    fn send(&mut self, message: Value) {
+        if self.ready {
+            self.write(&message).ok();
+        } else {
+            self.queued.push(message);
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn request(&mut self, request: Request, method: &str, params: Value) {
+        let id = self.next_id();
+        self.pending.insert(id, request);
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+    }
+
+    /// Whether the server stopped or could not be written to.
+    pub fn has_exited(&mut self) -> bool {
+        self.exited || self.child.try_wait().is_ok_and(|status| status.is_some())
+    }
+
+    pub fn is_open(&self, path: &Path) -> bool {
+        self.documents.contains_key(path)
+    }
+
+    /// Tells the server `path` is open with `lines` as its text.
+    pub fn did_open(&mut self, path: &Path, language: &str, lines: &[String]) {
+        let text = lines.join("\n") + "\n";
+        let params = json!({
+            "textDocument": { "uri": protocol::path_to_uri(path), "languageId": language, "version": 0, "text": text },
+        });
+        self.notify("textDocument/didOpen", params);
+        self.documents.insert(path.to_path_buf(), Document { version: 0, lines: lines.to_vec() });
+    }
+
+    /// Sends what changed in `path` since it was last sent, as one edit
+    /// covering the lines that differ. Nothing is sent if nothing changed.
+    pub fn did_change(&mut self, path: &Path, lines: &[String]) {
+        let Some(document) = self.documents.get_mut(path) else {
+            return;
+        };
+        let Some((range, text)) = change(&document.lines, lines) else {
+            return;
+        };
+        document.version += 1;
+        document.lines = lines.to_vec();
+        let params = json!({
+            "textDocument": { "uri": protocol::path_to_uri(path), "version": document.version },
+            "contentChanges": [{ "range": range.to_json(), "text": text }],
+        });
+        self.notify("textDocument/didChange", params);
+    }
+
+    pub fn did_save(&mut self, path: &Path) {
+        self.notify("textDocument/didSave", json!({ "textDocument": { "uri": protocol::path_to_uri(path) } }));
+    }
+
+    pub fn did_close(&mut self, path: &Path) {
+        if self.documents.remove(path).is_some() {
+            self.notify("textDocument/didClose", json!({ "textDocument": { "uri": protocol::path_to_uri(path) } }));
+        }
+    }
+
+    // TextDocumentPositionParams for byte `x` of line `y` in `path`.
+    fn position(&self, path: &Path, (x, y): (usize, usize)) -> Value {
+        let lines = self.documents.get(path).map_or(&[][..], |d| &d.lines[..]);
+        json!({
+            "textDocument": { "uri": protocol::path_to_uri(path) },
+            "position": Position::from_byte(lines, (x, y)).to_json(),
+        })
+    }
+
+    pub fn completion(&mut self, path: &Path, at: (usize, usize)) {
+        let params = self.position(path, at);
+        self.request(Request::Completion, "textDocument/completion", params);
+    }
+
+    pub fn hover(&mut self, path: &Path, at: (usize, usize)) {
+        let params = self.position(path, at);
+        self.request(Request::Hover, "textDocument/hover", params);
+    }
+
+    pub fn signature_help(&mut self, path: &Path, at: (usize, usize)) {
+        let params = self.position(path, at);
+        self.request(Request::SignatureHelp, "textDocument/signatureHelp", params);
+    }
+
+    pub fn definition(&mut self, path: &Path, at: (usize, usize)) {
+        let params = self.position(path, at);
+        self.request(Request::Definition, "textDocument/definition", params);
+    }
+
+    pub fn references(&mut self, path: &Path, at: (usize, usize)) {
+        let mut params = self.position(path, at);
+        params["context"] = json!({ "includeDeclaration": true });
+        self.request(Request::References, "textDocument/references", params);
+    }
+
+    pub fn rename(&mut self, path: &Path, at: (usize, usize), new_name: &str) {
+        let mut params = self.position(path, at);
+        params["newName"] = json!(new_name);
+        self.request(Request::Rename, "textDocument/rename", params);
+    }
+
+    pub fn formatting(&mut self, path: &Path, tab_size: usize, insert_spaces: bool) {
+        let params = json!({
+            "textDocument": { "uri": protocol::path_to_uri(path) },
+            "options": { "tabSize": tab_size, "insertSpaces": insert_spaces },
+        });
+        self.request(Request::Formatting(path.to_path_buf()), "textDocument/formatting", params);
+    }
+
+    /// Everything the server sent since the last call.
+    pub fn poll(&mut self) -> Vec<Update> {
+        let mut updates = Vec::new();
+        loop {
+            let received = self.incoming.get_mut().map_or(Err(TryRecvError::Disconnected), |incoming| incoming.try_recv());
+            match received {
+                Ok(message) => updates.extend(self.handle(message)),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.exited {
+                        self.exited = true;
+                        updates.push(Update::Error("language server exited".to_string()));
+                    }
+                    break;
+                }
+            }
+        }
+        updates
+    }
+
+    fn handle(&mut self, message: Value) -> Option<Update> {
+        let method = message.get("method").and_then(Value::as_str);
+        match (message.get("id").cloned(), method) {
+            // A request from the server: answer so it does not wait.
+            (Some(id), Some(_)) => {
+                self.write(&json!({ "jsonrpc": "2.0", "id": id, "result": null })).ok();
+                None
+            }
+            (None, Some("textDocument/publishDiagnostics")) => {
+                let (path, diagnostics) = Diagnostic::parse_published(message.get("params")?)?;
+                Some(Update::Diagnostics(path, diagnostics))
+            }
+            (None, _) => None,
+            (Some(id), None) => {
+                let request = self.pending.remove(&id.as_u64()?)?;
+                if let Some(error) = message.get("error") {
+                    let text = error.get("message").and_then(Value::as_str).unwrap_or("request failed");
+                    return Some(Update::Error(text.to_string()));
+                }
+                let result = message.get("result").unwrap_or(&Value::Null);
+                self.response(request, result)
+            }
+        }
+    }
+
+    fn response(&mut self, request: Request, result: &Value) -> Option<Update> {
+        match request {
+            Request::Initialize => {
+                self.trigger_characters = result
+                    .pointer("/capabilities/completionProvider/triggerCharacters")
+                    .and_then(Value::as_array)
+                    .map(|chars| chars.iter().filter_map(Value::as_str).map(String::from).collect())
+                    .unwrap_or_default();
+                self.ready = true;
+                self.write(&json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} })).ok();
+                for message in std::mem::take(&mut self.queued) {
+                    self.write(&message).ok();
+                }
+                None
+            }
+            Request::Completion => Some(Update::Completion(CompletionItem::parse_all(result))),
+            Request::Hover => {
+                let text = protocol::text(result.get("contents").unwrap_or(&Value::Null));
+                Some(Update::Hover(text))
+            }
+            Request::SignatureHelp => protocol::signature(result).map(Update::SignatureHelp),
+            Request::Definition => Some(Update::Definition(Location::parse_all(result))),
+            Request::References => Some(Update::References(Location::parse_all(result))),
+            Request::Rename => Some(Update::Rename(protocol::workspace_edit(result))),
+            Request::Formatting(path) => Some(Update::Formatting(path, TextEdit::parse_all(result))),
+            Request::Shutdown => None,
+        }
+    }
+
+    /// Asks the server to shut down and exit.
+    pub fn shutdown(&mut self) {
+        if self.ready && !self.exited {
+            let id = self.next_id();
+            self.pending.insert(id, Request::Shutdown);
+            self.write(&json!({ "jsonrpc": "2.0", "id": id, "method": "shutdown" })).ok();
+            self.write(&json!({ "jsonrpc": "2.0", "method": "exit" })).ok();
+        }
+        self.exited = true;
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.shutdown();
+        // Give a well-behaved server a moment before making sure it is gone.
+        for _ in 0..10 {
+            if self.child.try_wait().is_ok_and(|status| status.is_some()) {
+                return;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+/// The edit turning `old` into `new`: the range of lines between their
+/// common first and last lines, and the text replacing it. `None` if they
+/// are the same.
+pub fn change(old: &[String], new: &[String]) -> Option<(Range, String)> {
+    if old == new {
+        return None;
+    }
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let limit = old.len().min(new.len()) - prefix;
+    let suffix = old.iter().rev().zip(new.iter().rev()).take(limit).take_while(|(a, b)| a == b).count();
+    let range = Range {
+        start: Position { line: prefix, character: 0 },
+        end: Position { line: old.len() - suffix, character: 0 },
+    };
+    let text: String = new[prefix..new.len() - suffix].iter().map(|line| format!("{}\n", line)).collect();
+    Some((range, text))
+}
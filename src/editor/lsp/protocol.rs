@@ -0,0 +1,299 @@
+//! LSP message framing and the parts of the protocol's types the editor
+//! uses, read from and written to JSON.
+
+use crate::editor::diagnostics::Severity;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes `message` with its `Content-Length` header.
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// Reads the next message; `None` at the end of the stream. Headers other
+/// than `Content-Length` are skipped.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let mut body = vec![0; length.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// A position as the protocol counts it: columns in UTF-16 code units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+impl Position {
+    /// The position of byte `x` on line `y` of `lines`.
+    pub fn from_byte(lines: &[String], (x, y): (usize, usize)) -> Position {
+        let line = lines.get(y).map_or("", |l| l.as_str());
+        let x = x.min(line.len());
+        Position { line: y, character: line[..x].encode_utf16().count() }
+    }
+
+    /// The byte column of this position in `line`.
+    pub fn byte_in(&self, line: &str) -> usize {
+        let mut units = 0;
+        for (i, c) in line.char_indices() {
+            if units >= self.character {
+                return i;
+            }
+            units += c.len_utf16();
+        }
+        line.len()
+    }
+
+    fn parse(value: &Value) -> Option<Position> {
+        Some(Position {
+            line: value.get("line")?.as_u64()? as usize,
+            character: value.get("character")?.as_u64()? as usize,
+        })
+    }
+
+    pub fn to_json(self) -> Value {
+        json!({ "line": self.line, "character": self.character })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range {
+    fn parse(value: &Value) -> Option<Range> {
+        Some(Range { start: Position::parse(value.get("start")?)?, end: Position::parse(value.get("end")?)? })
+    }
+
+    pub fn to_json(self) -> Value {
+        json!({ "start": self.start.to_json(), "end": self.end.to_json() })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub range: Range,
+}
+
+impl Location {
+    fn parse(value: &Value) -> Option<Location> {
+        // LocationLink has targetUri and targetSelectionRange instead.
+        let uri = value.get("uri").or_else(|| value.get("targetUri"))?.as_str()?;
+        let range = value.get("range").or_else(|| value.get("targetSelectionRange"))?;
+        Some(Location { path: uri_to_path(uri)?, range: Range::parse(range)? })
+    }
+
+    /// The locations in a definition or references result, which may be a
+    /// single location, a list, or null.
+    pub fn parse_all(value: &Value) -> Vec<Location> {
+        match value {
+            Value::Array(items) => items.iter().filter_map(Location::parse).collect(),
+            Value::Null => Vec::new(),
+            single => Location::parse(single).into_iter().collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextEdit {
+    pub range: Range,
+    pub new_text: String,
+}
+
+impl TextEdit {
+    fn parse(value: &Value) -> Option<TextEdit> {
+        Some(TextEdit { range: Range::parse(value.get("range")?)?, new_text: value.get("newText")?.as_str()?.to_string() })
+    }
+
+    pub fn parse_all(value: &Value) -> Vec<TextEdit> {
+        value.as_array().map_or_else(Vec::new, |edits| edits.iter().filter_map(TextEdit::parse).collect())
+    }
+}
+
+/// Edits per file from a WorkspaceEdit, in either its `changes` or
+/// `documentChanges` form.
+pub fn workspace_edit(value: &Value) -> Vec<(PathBuf, Vec<TextEdit>)> {
+    let mut files = Vec::new();
+    if let Some(changes) = value.get("changes").and_then(Value::as_object) {
+        for (uri, edits) in changes {
+            if let Some(path) = uri_to_path(uri) {
+                files.push((path, TextEdit::parse_all(edits)));
+            }
+        }
+    }
+    for change in value.get("documentChanges").and_then(Value::as_array).into_iter().flatten() {
+        let uri = change.pointer("/textDocument/uri").and_then(Value::as_str);
+        if let (Some(path), Some(edits)) = (uri.and_then(uri_to_path), change.get("edits")) {
+            files.push((path, TextEdit::parse_all(edits)));
+        }
+    }
+    files
+}
+
+/// Applies `edits` to `lines`, a document whose every line ends in a
+/// newline. Edits do not overlap; they are applied from the end so
+/// earlier positions stay valid.
+pub fn apply_edits(lines: &mut Vec<String>, edits: &[TextEdit]) {
+    let mut text = lines.join("\n") + "\n";
+    let starts: Vec<usize> = lines.iter().scan(0, |at, line| Some(std::mem::replace(at, *at + line.len() + 1))).collect();
+    let offset = |pos: Position| match lines.get(pos.line) {
+        Some(line) => starts[pos.line] + pos.byte_in(line),
+        None => text.len(),
+    };
+    let mut edits: Vec<(usize, usize, &str)> =
+        edits.iter().map(|edit| (offset(edit.range.start), offset(edit.range.end), edit.new_text.as_str())).collect();
+    edits.sort_by_key(|&(start, end, _)| (start, end));
+    for (start, end, new_text) in edits.into_iter().rev() {
+        text.replace_range(start..end.max(start), new_text);
+    }
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    *lines = text.split('\n').map(String::from).collect();
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub range: Range,
+    pub severity: Severity,
+    pub message: String,
+    /// What produced it, such as `clang` or `clang-tidy`.
+    pub source: Option<String>,
+}
+
+impl Diagnostic {
+    fn parse(value: &Value) -> Option<Diagnostic> {
+        let severity = match value.get("severity").and_then(Value::as_u64) {
+            Some(2) => Severity::Warning,
+            Some(3) => Severity::Information,
+            Some(4) => Severity::Hint,
+            _ => Severity::Error,
+        };
+        Some(Diagnostic {
+            range: Range::parse(value.get("range")?)?,
+            severity,
+            message: value.get("message")?.as_str()?.to_string(),
+            source: value.get("source").and_then(Value::as_str).map(String::from),
+        })
+    }
+
+    /// The file and diagnostics of a publishDiagnostics notification.
+    pub fn parse_published(params: &Value) -> Option<(PathBuf, Vec<Diagnostic>)> {
+        let path = uri_to_path(params.get("uri")?.as_str()?)?;
+        let diagnostics = params.get("diagnostics")?.as_array()?.iter().filter_map(Diagnostic::parse).collect();
+        Some((path, diagnostics))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletionItem {
+    pub label: String,
+    /// The text to insert, from the text edit or insertText, or else the
+    /// label.
+    pub insert: String,
+    /// The protocol's CompletionItemKind.
+    pub kind: u64,
+    pub detail: String,
+    pub documentation: String,
+}
+
+impl CompletionItem {
+    fn parse(value: &Value) -> Option<CompletionItem> {
+        let label = value.get("label")?.as_str()?.trim().to_string();
+        let insert = value
+            .pointer("/textEdit/newText")
+            .or_else(|| value.get("insertText"))
+            .and_then(Value::as_str)
+            .map_or_else(|| label.clone(), String::from);
+        Some(CompletionItem {
+            label,
+            insert,
+            kind: value.get("kind").and_then(Value::as_u64).unwrap_or(0),
+            detail: value.get("detail").and_then(Value::as_str).unwrap_or("").to_string(),
+            documentation: text(value.get("documentation").unwrap_or(&Value::Null)),
+        })
+    }
+
+    /// The items of a completion result, a list or a CompletionList.
+    pub fn parse_all(value: &Value) -> Vec<CompletionItem> {
+        let items = value.get("items").unwrap_or(value);
+        items.as_array().map_or_else(Vec::new, |items| items.iter().filter_map(CompletionItem::parse).collect())
+    }
+}
+
+/// Plain text from a string, MarkupContent or MarkedString (or a list of
+/// them), as hover and documentation come.
+pub fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(parts) => parts.iter().map(text).filter(|t| !t.is_empty()).collect::<Vec<_>>().join("\n"),
+        Value::Object(object) => object.get("value").map_or_else(String::new, text),
+        _ => String::new(),
+    }
+}
+
+/// The active signature of a signatureHelp result, as a line of text.
+pub fn signature(value: &Value) -> Option<String> {
+    let signatures = value.get("signatures")?.as_array()?;
+    let active = value.get("activeSignature").and_then(Value::as_u64).unwrap_or(0) as usize;
+    let signature = signatures.get(active).or_else(|| signatures.first())?;
+    signature.get("label")?.as_str().map(String::from)
+}
+
+/// A `file://` URI for `path`, which is made absolute first.
+pub fn path_to_uri(path: &Path) -> String {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut uri = "file://".to_string();
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let hex = encoded.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match (encoded[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                bytes.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
@@ -0,0 +1,206 @@
+//! The client against `examples/fake_lsp.rs`, which `cargo test` builds
+//! alongside the tests.
+
+use super::protocol::{self, Diagnostic, Position, Range, TextEdit};
+use crate::editor::diagnostics::Severity;
+use super::{Client, Update};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+struct Fake {
+    dir: PathBuf,
+    client: Client,
+}
+
+impl Fake {
+    fn start(name: &str, script: Value) -> Fake {
+        let exe = std::env::current_exe().unwrap();
+        let server = exe.parent().and_then(Path::parent).unwrap().join("examples").join("fake_lsp");
+        assert!(server.exists(), "{} not built; run the tests with `cargo test`", server.display());
+        let dir = std::env::temp_dir().join(format!("vimr-lsp-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("script.json"), script.to_string()).unwrap();
+        let command = format!("{} {} {}", server.display(), dir.join("script.json").display(), dir.join("log").display());
+        let client = Client::start(&command, &dir).unwrap();
+        Fake { dir, client }
+    }
+
+    fn file(&self) -> PathBuf {
+        self.dir.join("a.cpp")
+    }
+
+    /// Polls until an update arrives, failing after a few seconds.
+    fn next_update(&mut self) -> Update {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if let Some(update) = self.client.poll().into_iter().next() {
+                return update;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        panic!("no update from the fake server");
+    }
+
+    /// The messages the server received, once it has exited.
+    fn received(mut self) -> Vec<Value> {
+        // Queued messages only go out once `initialize` is answered.
+        let start = Instant::now();
+        while !self.client.ready && start.elapsed() < Duration::from_secs(5) {
+            self.client.poll();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        self.client.shutdown();
+        while !self.client.has_exited() && start.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let log = fs::read_to_string(self.dir.join("log")).unwrap();
+        fs::remove_dir_all(&self.dir).ok();
+        log.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+}
+
+fn lines(text: &[&str]) -> Vec<String> {
+    text.iter().map(|s| s.to_string()).collect()
+}
+
+fn range(start: (usize, usize), end: (usize, usize)) -> Range {
+    Range {
+        start: Position { line: start.0, character: start.1 },
+        end: Position { line: end.0, character: end.1 },
+    }
+}
+
+#[test]
+fn initializes_before_sending_anything_else() {
+    let mut fake = Fake::start("init", json!({ "responses": { "textDocument/hover": { "contents": "int x" } } }));
+    let file = fake.file();
+    fake.client.did_open(&file, "cpp", &lines(&["int x;"]));
+    fake.client.hover(&file, (4, 0));
+    assert_eq!(fake.next_update(), Update::Hover("int x".to_string()));
+
+    let methods: Vec<String> =
+        fake.received().iter().filter_map(|m| m["method"].as_str().map(String::from)).collect();
+    assert_eq!(methods[..4], ["initialize", "initialized", "textDocument/didOpen", "textDocument/hover"]);
+}
+
+#[test]
+fn sends_only_the_changed_lines() {
+    let mut fake = Fake::start("change", json!({}));
+    let file = fake.file();
+    fake.client.did_open(&file, "cpp", &lines(&["a", "b", "c"]));
+    fake.client.did_change(&file, &lines(&["a", "b", "c"]));
+    fake.client.did_change(&file, &lines(&["a", "x", "y", "c"]));
+
+    let changes: Vec<Value> =
+        fake.received().into_iter().filter(|m| m["method"] == "textDocument/didChange").collect();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0]["params"]["textDocument"]["version"], 1);
+    assert_eq!(changes[0]["params"]["contentChanges"][0]["range"], range((1, 0), (2, 0)).to_json());
+    assert_eq!(changes[0]["params"]["contentChanges"][0]["text"], "x\ny\n");
+}
+
+#[test]
+fn positions_count_utf16_units() {
+    let mut fake = Fake::start("utf16", json!({}));
+    let file = fake.file();
+    fake.client.did_open(&file, "cpp", &lines(&["s = \"é😀\"; x"]));
+    // Byte 12, the `;`, is UTF-16 column 9.
+    fake.client.completion(&file, (12, 0));
+    fake.next_update();
+
+    let request = fake.received().into_iter().find(|m| m["method"] == "textDocument/completion").unwrap();
+    assert_eq!(request["params"]["position"], json!({ "line": 0, "character": 9 }));
+}
+
+#[test]
+fn completion_items_and_trigger_characters() {
+    let script = json!({
+        "capabilities": { "completionProvider": { "triggerCharacters": [".", ">", ":"] } },
+        "responses": { "textDocument/completion": { "isIncomplete": false, "items": [
+            { "label": " push_back(const int &x)", "insertText": "push_back", "kind": 2, "detail": "void" },
+            { "label": "size", "textEdit": { "range": range((0, 2), (0, 2)).to_json(), "newText": "size" } },
+        ] } },
+    });
+    let mut fake = Fake::start("completion", script);
+    let file = fake.file();
+    fake.client.did_open(&file, "cpp", &lines(&["v."]));
+    fake.client.completion(&file, (2, 0));
+    let Update::Completion(items) = fake.next_update() else {
+        panic!("expected completion items");
+    };
+    assert_eq!(fake.client.trigger_characters, [".", ">", ":"]);
+    assert_eq!(items.len(), 2);
+    assert_eq!((items[0].label.as_str(), items[0].insert.as_str()), ("push_back(const int &x)", "push_back"));
+    assert_eq!((items[0].kind, items[0].detail.as_str()), (2, "void"));
+    assert_eq!(items[1].insert, "size");
+}
+
+#[test]
+fn publishes_diagnostics() {
+    let script = json!({ "on_open": [{ "method": "textDocument/publishDiagnostics", "params": {
+        "uri": "file:///tmp/a%20b.cpp",
+        "diagnostics": [{ "range": range((0, 4), (0, 5)).to_json(), "severity": 2, "message": "unused", "source": "clang" }],
+    } }] });
+    let mut fake = Fake::start("diagnostics", script);
+    let file = fake.file();
+    fake.client.did_open(&file, "cpp", &lines(&["int x;"]));
+    let expected = Diagnostic {
+        range: range((0, 4), (0, 5)),
+        severity: Severity::Warning,
+        message: "unused".to_string(),
+        source: Some("clang".to_string()),
+    };
+    assert_eq!(fake.next_update(), Update::Diagnostics(PathBuf::from("/tmp/a b.cpp"), vec![expected]));
+}
+
+#[test]
+fn navigation_and_edits() {
+    let location = json!({ "uri": "file:///src/a.cpp", "range": range((3, 1), (3, 4)).to_json() });
+    let edit = json!({ "range": range((0, 4), (0, 5)).to_json(), "newText": "count" });
+    let script = json!({ "responses": {
+        "textDocument/definition": location,
+        "textDocument/references": [location, location],
+        "textDocument/rename": { "changes": { "file:///src/a.cpp": [edit] } },
+        "textDocument/formatting": [edit],
+        "textDocument/signatureHelp": { "signatures": [{ "label": "int f(int a)" }], "activeSignature": 0 },
+    } });
+    let mut fake = Fake::start("navigation", script);
+    let file = fake.file();
+    fake.client.did_open(&file, "cpp", &lines(&["int x;"]));
+
+    fake.client.definition(&file, (4, 0));
+    let Update::Definition(found) = fake.next_update() else {
+        panic!("expected a definition");
+    };
+    assert_eq!((found[0].path.as_path(), found[0].range), (Path::new("/src/a.cpp"), range((3, 1), (3, 4))));
+
+    fake.client.references(&file, (4, 0));
+    assert!(matches!(fake.next_update(), Update::References(found) if found.len() == 2));
+
+    let text_edit = TextEdit { range: range((0, 4), (0, 5)), new_text: "count".to_string() };
+    fake.client.rename(&file, (4, 0), "count");
+    assert_eq!(fake.next_update(), Update::Rename(vec![(PathBuf::from("/src/a.cpp"), vec![text_edit.clone()])]));
+
+    fake.client.formatting(&file, 4, true);
+    assert_eq!(fake.next_update(), Update::Formatting(file.clone(), vec![text_edit]));
+
+    fake.client.signature_help(&file, (4, 0));
+    assert_eq!(fake.next_update(), Update::SignatureHelp("int f(int a)".to_string()));
+
+    let rename = fake.received().into_iter().find(|m| m["method"] == "textDocument/rename").unwrap();
+    assert_eq!(rename["params"]["newName"], "count");
+}
+
+#[test]
+fn applies_edits_from_the_end() {
+    let mut text = lines(&["int x = 1;", "int y = x;"]);
+    let edits = [
+        TextEdit { range: range((0, 4), (0, 5)), new_text: "value".to_string() },
+        TextEdit { range: range((1, 8), (1, 9)), new_text: "value".to_string() },
+        TextEdit { range: range((1, 10), (2, 0)), new_text: "\n\nint z;\n".to_string() },
+    ];
+    protocol::apply_edits(&mut text, &edits);
+    assert_eq!(text, lines(&["int value = 1;", "int y = value;", "", "int z;"]));
+}
@@ -0,0 +1,84 @@
+//! Shell commands run in the background, like `:make`, so the editor keeps
+//! responding while they work.
+
+use std::path::Path;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct Output {
+    pub success: bool,
+    /// Standard error followed by standard output, where compilers put
+    /// their messages.
+    pub text: String,
+    pub elapsed: Duration,
+}
+
+pub struct Job {
+    pub command: String,
+    // In a mutex only so the editor view holding the job is `Sync`.
+    finished: Mutex<Receiver<Output>>,
+}
+
+impl Job {
+    /// Starts `command` with `sh -c`.
+    pub fn start(command: &str) -> Job {
+        let (sender, finished) = mpsc::channel();
+        let shell_command = command.to_string();
+        thread::spawn(move || {
+            let started = Instant::now();
+            let output = match Command::new("sh").arg("-c").arg(&shell_command).output() {
+                Ok(output) => Output {
+                    success: output.status.success(),
+                    text: String::from_utf8_lossy(&output.stderr).into_owned() + &String::from_utf8_lossy(&output.stdout),
+                    elapsed: started.elapsed(),
+                },
+                Err(e) => Output { success: false, text: format!("sh: {}", e), elapsed: started.elapsed() },
+            };
+            sender.send(output).ok();
+        });
+        Job { command: command.to_string(), finished: Mutex::new(finished) }
+    }
+
+    /// The output, once the command has finished.
+    pub fn poll(&mut self) -> Option<Output> {
+        self.finished.get_mut().ok()?.try_recv().ok()
+    }
+}
+
+/// Quotes `word` for `sh` if it has anything the shell would interpret.
+pub fn shell_quote(word: &str) -> String {
+    if !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || "/._-+,=:@".contains(c)) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// `command` with `%` replaced by the file name and `%<` by the name
+/// without its extension, both quoted for the shell. `%%` is a literal `%`.
+pub fn expand(command: &str, filename: &str) -> String {
+    let stem = Path::new(filename).with_extension("");
+    let mut expanded = String::new();
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('<') => {
+                chars.next();
+                expanded.push_str(&shell_quote(&stem.to_string_lossy()));
+            }
+            Some('%') => {
+                chars.next();
+                expanded.push('%');
+            }
+            _ => expanded.push_str(&shell_quote(filename)),
+        }
+    }
+    expanded
+}
@@ -1,4 +1,16 @@
 pub mod text_screen;
+pub mod completion;
+pub mod diagnostics;
+pub mod lsp;
+pub mod make;
 pub mod modes;
+pub mod panel;
+pub mod quickfix;
+pub mod run;
 pub mod snippets;
 pub mod io;
+pub mod config;
+pub mod keymap;
+pub mod gutter;
+pub mod viewport;
+pub mod statusline;
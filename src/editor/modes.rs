@@ -1,6 +1,55 @@
+use crate::utils::vec2::Vec2;
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum EditorMode {
     Normal,
     Insert,
     Command,
+    Visual,
+    VisualLine,
+}
+
+/// A visual mode selection, with `start` before `end`. Both ends are
+/// included, as in vim.
+#[derive(Clone, Copy)]
+pub struct Selection {
+    pub start: Vec2,
+    pub end: Vec2,
+    pub linewise: bool,
+}
+
+impl Selection {
+    pub fn new(a: Vec2, b: Vec2, linewise: bool) -> Self {
+        let (start, end) = if (a.y, a.x) <= (b.y, b.x) { (a, b) } else { (b, a) };
+        Selection { start, end, linewise }
+    }
+
+    /// The selected byte range of `line`, whose text is `text`.
+    pub fn columns(&self, line: usize, text: &str) -> Option<(usize, usize)> {
+        if line < self.start.y || line > self.end.y {
+            return None;
+        }
+        if self.linewise {
+            return Some((0, text.len()));
+        }
+        let start = if line == self.start.y { self.start.x.min(text.len()) } else { 0 };
+        let end = if line == self.end.y {
+            // Include the character under the end position.
+            let x = self.end.x.min(text.len());
+            x + text[x..].chars().next().map_or(0, char::len_utf8)
+        } else {
+            text.len()
+        };
+        Some((start, end.max(start)))
+    }
+
+    /// The selected text, one entry per line.
+    pub fn text(&self, content: &[String]) -> Vec<String> {
+        (self.start.y..=self.end.y.min(content.len().saturating_sub(1)))
+            .filter_map(|line| {
+                let text = &content[line];
+                self.columns(line, text).map(|(start, end)| text[start..end].to_string())
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,158 @@
+//! A list shown in a strip above the status line, such as the quickfix
+//! window, with its own selection. The text stays in view above it. The
+//! strip can also be split into panes side by side that scroll together.
+
+use crate::editor::quickfix::ListKind;
+use cursive::theme::{BaseColor, Color, ColorStyle, PaletteColor};
+use cursive::Printer;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PanelKind {
+    List(ListKind),
+    /// The verdicts of `:Run`.
+    Run,
+    /// The tests stored for the file.
+    Tests,
+    /// The expected and actual output of the test `:Stress` failed on.
+    Stress,
+}
+
+pub struct Panel {
+    pub kind: PanelKind,
+    pub title: String,
+    pub lines: Vec<String>,
+    /// Titles and lines of the panes shown side by side in place of
+    /// `lines`, if it is split; `lines` then has a line per row.
+    splits: Vec<(String, Vec<String>)>,
+    pub selected: usize,
+    /// First line in view.
+    top: usize,
+    /// Whether keys go to the panel rather than the text.
+    pub focused: bool,
+}
+
+impl Panel {
+    /// Most rows it takes, its title bar included.
+    const MAX_HEIGHT: usize = 10;
+
+    pub fn new(kind: PanelKind, title: &str, lines: Vec<String>, selected: usize) -> Panel {
+        let mut panel =
+            Panel { kind, title: String::new(), lines: Vec::new(), splits: Vec::new(), selected: 0, top: 0, focused: true };
+        panel.set_lines(title, lines, selected);
+        panel
+    }
+
+    /// A panel split into `splits`, each a title and its lines. Rows where
+    /// the first two panes differ are highlighted.
+    pub fn split(kind: PanelKind, splits: Vec<(String, Vec<String>)>) -> Panel {
+        let rows = splits.iter().map(|(_, lines)| lines.len()).max().unwrap_or(0);
+        let lines = (0..rows)
+            .map(|row| {
+                let cells: Vec<&str> = splits.iter().map(|(_, lines)| lines.get(row).map_or("", String::as_str)).collect();
+                cells.join(" | ")
+            })
+            .collect();
+        let title = splits.iter().map(|(title, _)| title.as_str()).collect::<Vec<_>>().join(" | ");
+        let mut panel = Panel::new(kind, &title, lines, 0);
+        panel.splits = splits;
+        panel
+    }
+
+    /// Replaces what it shows, keeping the view where it was if it can.
+    pub fn set_lines(&mut self, title: &str, lines: Vec<String>, selected: usize) {
+        self.title = title.to_string();
+        self.lines = lines;
+        self.select(selected);
+    }
+
+    /// Rows it takes on the screen.
+    pub fn height(&self) -> usize {
+        self.lines.len().clamp(1, Self::MAX_HEIGHT - 1) + 1
+    }
+
+    pub fn select(&mut self, index: usize) {
+        self.selected = index.min(self.lines.len().saturating_sub(1));
+        let rows = self.height() - 1;
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + rows {
+            self.top = self.selected + 1 - rows;
+        }
+        self.top = self.top.min(self.lines.len().saturating_sub(rows));
+    }
+
+    pub fn move_by(&mut self, delta: isize) {
+        self.select(self.selected.saturating_add_signed(delta));
+    }
+
+    /// Draws it from row `y` down.
+    pub fn draw(&self, printer: &Printer, y: usize) {
+        let width = printer.size.x;
+        let bar = if self.focused { Color::Dark(BaseColor::Blue) } else { Color::Dark(BaseColor::Black) };
+        printer.with_color(ColorStyle::new(Color::Light(BaseColor::White), bar), |printer| {
+            printer.print_hline((0, y), width, " ");
+            if self.splits.is_empty() {
+                printer.print((0, y), &format!(" {}", self.title));
+            }
+            for (i, (title, _)) in self.splits.iter().enumerate() {
+                let (x, pane) = self.pane(i, width);
+                printer.print((x, y), &format!(" {}", title).chars().take(pane).collect::<String>());
+            }
+        });
+        if !self.splits.is_empty() {
+            self.draw_splits(printer, y);
+            return;
+        }
+
+        let selected = ColorStyle::new(Color::Light(BaseColor::White), Color::Dark(BaseColor::Cyan));
+        for (row, index) in (self.top..self.lines.len()).take(self.height() - 1).enumerate() {
+            let text: String = self.lines[index].chars().take(width).collect();
+            if index == self.selected {
+                printer.with_color(selected, |printer| {
+                    printer.print_hline((0, y + 1 + row), width, " ");
+                    printer.print((0, y + 1 + row), &text);
+                });
+            } else {
+                printer.with_color(ColorStyle::new(PaletteColor::Primary, PaletteColor::View), |printer| {
+                    printer.print((0, y + 1 + row), &text);
+                });
+            }
+        }
+    }
+
+    /// Column and width of pane `i` of a split panel `width` wide, leaving
+    /// a column between panes for the separator.
+    fn pane(&self, i: usize, width: usize) -> (usize, usize) {
+        let count = self.splits.len();
+        let x = width * i / count;
+        (x, (width * (i + 1) / count).saturating_sub(x + 1))
+    }
+
+    fn draw_splits(&self, printer: &Printer, y: usize) {
+        let width = printer.size.x;
+        let text = ColorStyle::new(PaletteColor::Primary, PaletteColor::View);
+        let differs = ColorStyle::new(Color::Light(BaseColor::Red), PaletteColor::View);
+        let selected = ColorStyle::new(Color::Light(BaseColor::White), Color::Dark(BaseColor::Cyan));
+        for (row, index) in (self.top..self.lines.len()).take(self.height() - 1).enumerate() {
+            let cell = |i: usize| self.splits.get(i).and_then(|(_, lines)| lines.get(index));
+            let style = if index == self.selected {
+                selected
+            } else if self.splits.len() > 1 && cell(0) != cell(1) {
+                differs
+            } else {
+                text
+            };
+            printer.with_color(style, |printer| {
+                printer.print_hline((0, y + 1 + row), width, " ");
+                for i in 0..self.splits.len() {
+                    let (x, pane) = self.pane(i, width);
+                    if i > 0 {
+                        printer.print((x - 1, y + 1 + row), "│");
+                    }
+                    let line: String = cell(i).map_or("", String::as_str).chars().take(pane).collect();
+                    printer.print((x, y + 1 + row), &line);
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,507 @@
+//! Quickfix and location lists: positions in files with a message each,
+//! read from compiler output or found by `:grep`.
+
+use crate::editor::diagnostics::Severity;
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+/// Which of the two lists a command works on: the quickfix list, or the
+/// window's location list (the `:l` commands).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListKind {
+    Quickfix,
+    Location,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// The file as the compiler printed it; empty for messages without a
+    /// position, such as linker errors.
+    pub path: PathBuf,
+    /// Zero-based line and byte column.
+    pub line: usize,
+    pub col: usize,
+    /// None for matches that aren't errors or warnings, such as `:grep`'s.
+    pub severity: Option<Severity>,
+    pub text: String,
+}
+
+impl Entry {
+    /// Whether the entry points somewhere that can be jumped to.
+    pub fn is_valid(&self) -> bool {
+        !self.path.as_os_str().is_empty()
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Some(Severity::Error)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct List {
+    /// What produced the list, such as the command that was run.
+    pub title: String,
+    pub entries: Vec<Entry>,
+    /// The entry last jumped to.
+    pub current: usize,
+}
+
+impl List {
+    pub fn new(title: &str, entries: Vec<Entry>) -> List {
+        List { title: title.to_string(), entries, current: 0 }
+    }
+
+    pub fn errors(&self) -> usize {
+        self.entries.iter().filter(|e| e.is_error()).count()
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.entries.iter().filter(|e| e.severity == Some(Severity::Warning)).count()
+    }
+
+    /// The valid entry after (or before, unless `forward`) the current one.
+    pub fn next(&self, forward: bool) -> Option<usize> {
+        let valid = |&i: &usize| self.entries[i].is_valid();
+        if forward {
+            (self.current + 1..self.entries.len()).find(valid)
+        } else {
+            (0..self.current).rev().find(valid)
+        }
+    }
+}
+
+/// A command on a list, the same for both kinds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListCommand {
+    Open,
+    Close,
+    /// `:clist`: the valid entries, numbered.
+    List,
+    Next,
+    Previous,
+    First,
+    Last,
+    /// `:cc N`: entry N, or the current one again.
+    Go,
+    Older,
+    Newer,
+}
+
+/// The list and command an Ex command name stands for, as in `cnext` or
+/// `lopen` (and their short forms).
+pub fn command(name: &str) -> Option<(ListKind, ListCommand)> {
+    let kind = match name.chars().next()? {
+        'c' => ListKind::Quickfix,
+        'l' => ListKind::Location,
+        _ => return None,
+    };
+    // Matched without the list's letter, so `cl` here is `:ccl`; `:cl`
+    // itself is `:clist`, while `:ll` is `:ll N`.
+    let command = match &name[1..] {
+        "open" | "ope" | "window" | "w" => ListCommand::Open,
+        "close" | "clo" | "cl" => ListCommand::Close,
+        "list" | "li" => ListCommand::List,
+        "l" if kind == ListKind::Quickfix => ListCommand::List,
+        "next" | "ne" | "n" => ListCommand::Next,
+        "previous" | "prev" | "p" | "Next" | "N" => ListCommand::Previous,
+        "first" | "fir" | "rewind" | "r" => ListCommand::First,
+        "last" | "la" => ListCommand::Last,
+        "older" | "ol" => ListCommand::Older,
+        "newer" | "new" => ListCommand::Newer,
+        rest if rest == &name[..1] => ListCommand::Go,
+        _ => return None,
+    };
+    Some((kind, command))
+}
+
+/// The lists made so far, oldest first, for `:colder` and `:cnewer`.
+#[derive(Default)]
+pub struct Stack {
+    lists: Vec<List>,
+    current: usize,
+}
+
+impl Stack {
+    /// How many lists are remembered.
+    const DEPTH: usize = 10;
+
+    /// Makes `list` the current one. Lists newer than the current one are
+    /// dropped, as are the oldest past the limit.
+    pub fn push(&mut self, list: List) {
+        self.lists.truncate(self.current + 1);
+        self.lists.push(list);
+        if self.lists.len() > Self::DEPTH {
+            self.lists.remove(0);
+        }
+        self.current = self.lists.len() - 1;
+    }
+
+    pub fn current(&self) -> Option<&List> {
+        self.lists.get(self.current)
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut List> {
+        self.lists.get_mut(self.current)
+    }
+
+    /// Moves `count` lists back in time, or forward if `newer`.
+    pub fn go(&mut self, count: usize, newer: bool) -> Result<(), String> {
+        if newer {
+            if self.current + 1 >= self.lists.len() {
+                return Err("E381: At top of quickfix stack".to_string());
+            }
+            self.current = (self.current + count).min(self.lists.len() - 1);
+        } else {
+            if self.current == 0 {
+                return Err("E380: At bottom of quickfix stack".to_string());
+            }
+            self.current = self.current.saturating_sub(count);
+        }
+        Ok(())
+    }
+
+    /// `error list 2 of 3; 5 errors  :make` for the current list.
+    pub fn describe(&self) -> String {
+        let Some(list) = self.current() else {
+            return "No lists".to_string();
+        };
+        let count = list.entries.len();
+        format!("error list {} of {}; {} error{}  {}", self.current + 1, self.lists.len(), count, if count == 1 { "" } else { "s" }, list.title)
+    }
+}
+
+// `file:line:col: severity: message`, the column and severity optional.
+// Template backtraces (`file:line:col:   required from here`) have no
+// severity.
+static LOCATED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(.+?):(\d+):(?:(\d+):)?\s*(?:(fatal error|error|warning|note):\s*)?(.*)$").unwrap()
+});
+
+// Errors printed without a position, by the linker or the compiler
+// driver. Other lines without one, such as `In function 'void
+// report_error()':` or `2 errors generated.`, are not errors.
+const UNLOCATED_ERRORS: [&str; 4] = [": error:", ": fatal error:", "collect2: error", "undefined reference"];
+
+/// Entries for the messages in GCC or Clang output. Notes and template
+/// backtraces become notes; errors without a position (from the linker)
+/// are kept without one. Source excerpts and context lines such as
+/// `In function 'int main()':` are left out.
+pub fn parse_gcc(output: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for line in output.lines() {
+        if line.starts_with(' ') {
+            // Source excerpts and carets.
+            continue;
+        }
+        if let Some(captures) = LOCATED.captures(line) {
+            let number = |i: usize| captures.get(i).and_then(|m| m.as_str().parse::<usize>().ok());
+            let (Some(line_number), path) = (number(2), &captures[1]) else {
+                continue;
+            };
+            if path.contains(": ") || path.starts_with("In file included from") || path.trim_start().starts_with("from ") {
+                continue;
+            }
+            let severity = match captures.get(4).map(|m| m.as_str()) {
+                Some("error" | "fatal error") => Severity::Error,
+                Some("warning") => Severity::Warning,
+                _ => Severity::Information,
+            };
+            let severity = Some(severity);
+            entries.push(Entry {
+                path: PathBuf::from(path),
+                line: line_number.saturating_sub(1),
+                col: number(3).unwrap_or(1).saturating_sub(1),
+                severity,
+                text: captures[5].trim().to_string(),
+            });
+        } else if UNLOCATED_ERRORS.iter().any(|error| line.contains(error)) {
+            entries.push(Entry {
+                path: PathBuf::new(),
+                line: 0,
+                col: 0,
+                severity: Some(Severity::Error),
+                text: line.trim().to_string(),
+            });
+        }
+    }
+    entries
+}
+
+/// A line for each match of `pattern` in the files under `root`, skipping
+/// hidden directories, `target` and files that aren't text. Paths are
+/// relative to `root`.
+pub fn grep(pattern: &Regex, root: &Path) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let Ok(read) = fs::read_dir(&directory) else {
+            continue;
+        };
+        let mut children: Vec<PathBuf> = read.filter_map(|e| e.ok().map(|e| e.path())).collect();
+        children.sort();
+        let mut subdirectories = Vec::new();
+        for path in children {
+            let name = path.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned());
+            if name.starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                if name != "target" {
+                    subdirectories.push(path);
+                }
+                continue;
+            }
+            let Ok(text) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            for (line, content) in text.lines().enumerate() {
+                if let Some(found) = pattern.find(content) {
+                    entries.push(Entry { path: relative.clone(), line, col: found.start(), severity: None, text: content.trim().to_string() });
+                }
+            }
+        }
+        // Popped last first, so they are searched in order.
+        directories.extend(subdirectories.into_iter().rev());
+    }
+    entries
+}
+
+/// The entry's text, after `error:` or the like if it has a severity.
+pub fn message(entry: &Entry) -> String {
+    match entry.severity {
+        Some(Severity::Information) => format!("note: {}", entry.text),
+        Some(severity) => format!("{}: {}", severity.label(), entry.text),
+        None => entry.text.clone(),
+    }
+}
+
+/// `file:line: message` for the list window; just the message if there
+/// is no position.
+pub fn describe(entry: &Entry) -> String {
+    if entry.is_valid() {
+        format!("{}:{}: {}", entry.path.display(), entry.line + 1, message(entry))
+    } else {
+        message(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (path, line, col, severity, text) with one-based line and column, as
+    // the compiler prints them.
+    fn parsed(output: &str) -> Vec<(String, usize, usize, Option<Severity>, String)> {
+        parse_gcc(output)
+            .into_iter()
+            .map(|e| (e.path.display().to_string(), e.line + 1, e.col + 1, e.severity, e.text))
+            .collect()
+    }
+
+    fn entry(path: &str, line: usize, col: usize, severity: Severity, text: &str) -> (String, usize, usize, Option<Severity>, String) {
+        (path.to_string(), line, col, Some(severity), text.to_string())
+    }
+
+    #[test]
+    fn parses_gcc_output() {
+        // g++ 12, including a header, a template backtrace and notes, with
+        // `main` renamed so its context line mentions an error.
+        let output = r#"In file included from a.cpp:3:
+util.h: In function 'int f()':
+util.h:1:25: error: invalid conversion from 'const char*' to 'int' [-fpermissive]
+    1 | inline int f() { return "x"; }
+      |                         ^~~
+      |                         |
+      |                         const char*
+a.cpp: In function 'void report_error()':
+a.cpp:10:12: error: 'missing' was not declared in this scope
+   10 |     return missing;
+      |            ^~~~~~~
+a.cpp:7:9: warning: unused variable 'unused' [-Wunused-variable]
+    7 |     int unused;
+      |         ^~~~~~
+In file included from /usr/include/c++/12/bits/refwrap.h:39,
+                 from /usr/include/c++/12/vector:66,
+                 from a.cpp:1:
+/usr/include/c++/12/bits/stl_function.h: In instantiation of 'constexpr bool std::less<_Tp>::operator()(const _Tp&, const _Tp&) const [with _Tp = P]':
+/usr/include/c++/12/bits/stl_map.h:529:32:   required from 'std::map<_Key, _Tp, _Compare, _Alloc>::mapped_type& std::map<_Key, _Tp, _Compare, _Alloc>::operator[](key_type&&) [with _Key = P; _Tp = int]'
+a.cpp:9:11:   required from here
+/usr/include/c++/12/bits/stl_function.h:408:20: error: no match for 'operator<' (operand types are 'const P' and 'const P')
+  408 |       { return __x < __y; }
+      |                ~~~~^~~~~
+/usr/include/c++/12/bits/stl_pair.h:663:5: note: candidate: 'template<class _T1, class _T2> constexpr bool std::operator<(const pair<_T1, _T2>&, const pair<_T1, _T2>&)'
+  663 |     operator<(const pair<_T1, _T2>& __x, const pair<_T1, _T2>& __y)
+      |     ^~~~~~~~
+/usr/include/c++/12/bits/stl_pair.h:663:5: note:   template argument deduction/substitution failed:
+"#;
+        assert_eq!(
+            parsed(output),
+            vec![
+                entry("util.h", 1, 25, Severity::Error, "invalid conversion from 'const char*' to 'int' [-fpermissive]"),
+                entry("a.cpp", 10, 12, Severity::Error, "'missing' was not declared in this scope"),
+                entry("a.cpp", 7, 9, Severity::Warning, "unused variable 'unused' [-Wunused-variable]"),
+                entry(
+                    "/usr/include/c++/12/bits/stl_map.h",
+                    529,
+                    32,
+                    Severity::Information,
+                    "required from 'std::map<_Key, _Tp, _Compare, _Alloc>::mapped_type& std::map<_Key, _Tp, _Compare, _Alloc>::operator[](key_type&&) [with _Key = P; _Tp = int]'",
+                ),
+                entry("a.cpp", 9, 11, Severity::Information, "required from here"),
+                entry(
+                    "/usr/include/c++/12/bits/stl_function.h",
+                    408,
+                    20,
+                    Severity::Error,
+                    "no match for 'operator<' (operand types are 'const P' and 'const P')",
+                ),
+                entry(
+                    "/usr/include/c++/12/bits/stl_pair.h",
+                    663,
+                    5,
+                    Severity::Information,
+                    "candidate: 'template<class _T1, class _T2> constexpr bool std::operator<(const pair<_T1, _T2>&, const pair<_T1, _T2>&)'",
+                ),
+                entry("/usr/include/c++/12/bits/stl_pair.h", 663, 5, Severity::Information, "template argument deduction/substitution failed:"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_clang_output() {
+        let output = r#"a.cpp:10:12: error: use of undeclared identifier 'missing'
+   10 |     return missing;
+      |            ^
+a.cpp:7:9: warning: unused variable 'unused' [-Wunused-variable]
+    7 |     int unused;
+      |         ^~~~~~
+In file included from a.cpp:2:
+In file included from /usr/include/c++/12/map:60:
+/usr/include/c++/12/bits/stl_function.h:408:20: error: invalid operands to binary expression ('const P' and 'const P')
+  408 |       { return __x < __y; }
+      |                ~~~ ^ ~~~
+a.cpp:9:6: note: in instantiation of member function 'std::map<P, int>::operator[]' requested here
+    9 |     m[P{1}] = 2;
+      |      ^
+2 errors and 1 warning generated.
+"#;
+        assert_eq!(
+            parsed(output),
+            vec![
+                entry("a.cpp", 10, 12, Severity::Error, "use of undeclared identifier 'missing'"),
+                entry("a.cpp", 7, 9, Severity::Warning, "unused variable 'unused' [-Wunused-variable]"),
+                entry(
+                    "/usr/include/c++/12/bits/stl_function.h",
+                    408,
+                    20,
+                    Severity::Error,
+                    "invalid operands to binary expression ('const P' and 'const P')",
+                ),
+                entry("a.cpp", 9, 6, Severity::Information, "in instantiation of member function 'std::map<P, int>::operator[]' requested here"),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_unlocated_errors() {
+        let gcc = "/usr/bin/ld: /tmp/ccPAG3jC.o: in function `main':
+b.cpp:(.text+0x5): undefined reference to `helper()'
+collect2: error: ld returned 1 exit status
+";
+        let clang = "/usr/bin/ld: /tmp/b-3f2a1c.o: in function `main':
+b.cpp:(.text+0x5): undefined reference to `helper()'
+clang: error: linker command failed with exit code 1 (use -v to see invocation)
+";
+        let driver = "cc1plus: fatal error: missing.cpp: No such file or directory\ncompilation terminated.\n";
+        let unlocated = |text: &str| (String::new(), 1, 1, Some(Severity::Error), text.to_string());
+        assert_eq!(
+            parsed(gcc),
+            vec![unlocated("b.cpp:(.text+0x5): undefined reference to `helper()'"), unlocated("collect2: error: ld returned 1 exit status")]
+        );
+        assert_eq!(
+            parsed(clang),
+            vec![
+                unlocated("b.cpp:(.text+0x5): undefined reference to `helper()'"),
+                unlocated("clang: error: linker command failed with exit code 1 (use -v to see invocation)"),
+            ]
+        );
+        assert_eq!(parsed(driver), vec![unlocated("cc1plus: fatal error: missing.cpp: No such file or directory")]);
+    }
+
+    #[test]
+    fn reads_command_abbreviations() {
+        use ListCommand::*;
+        use ListKind::*;
+        let cases = [
+            ("cl", Some((Quickfix, List))),
+            ("clist", Some((Quickfix, List))),
+            ("ccl", Some((Quickfix, Close))),
+            ("cclose", Some((Quickfix, Close))),
+            ("cc", Some((Quickfix, Go))),
+            ("cw", Some((Quickfix, Open))),
+            ("cn", Some((Quickfix, Next))),
+            ("cN", Some((Quickfix, Previous))),
+            ("col", Some((Quickfix, Older))),
+            ("cnew", Some((Quickfix, Newer))),
+            ("ll", Some((Location, Go))),
+            ("lli", Some((Location, List))),
+            ("lcl", Some((Location, Close))),
+            ("lopen", Some((Location, Open))),
+            ("cx", None),
+            ("set", None),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(command(name), expected, "{}", name);
+        }
+    }
+
+    // A stack holding lists titled 1 to `count`, the last current.
+    fn stack(count: usize) -> Stack {
+        let mut stack = Stack::default();
+        for i in 1..=count {
+            stack.push(List::new(&i.to_string(), Vec::new()));
+        }
+        stack
+    }
+
+    fn title(stack: &Stack) -> &str {
+        &stack.current().unwrap().title
+    }
+
+    #[test]
+    fn keeps_the_last_ten_lists() {
+        let mut lists = stack(12);
+        assert_eq!(title(&lists), "12");
+        assert_eq!(lists.go(20, false), Ok(()));
+        assert_eq!(title(&lists), "3");
+        assert_eq!(lists.describe(), "error list 1 of 10; 0 errors  3");
+        assert_eq!(lists.go(4, true), Ok(()));
+        assert_eq!(title(&lists), "7");
+    }
+
+    #[test]
+    fn pushing_drops_newer_lists() {
+        let mut lists = stack(5);
+        lists.go(2, false).unwrap();
+        lists.push(List::new("new", Vec::new()));
+        assert_eq!(lists.describe(), "error list 4 of 4; 0 errors  new");
+        assert_eq!(lists.go(1, false), Ok(()));
+        assert_eq!(title(&lists), "3");
+    }
+
+    #[test]
+    fn stops_at_either_end_of_the_stack() {
+        assert_eq!(Stack::default().go(1, false), Err("E380: At bottom of quickfix stack".to_string()));
+        assert_eq!(Stack::default().go(1, true), Err("E381: At top of quickfix stack".to_string()));
+        let mut lists = stack(3);
+        assert_eq!(lists.go(1, true), Err("E381: At top of quickfix stack".to_string()));
+        assert_eq!(title(&lists), "3");
+        lists.go(5, false).unwrap();
+        assert_eq!(title(&lists), "1");
+        assert_eq!(lists.go(1, false), Err("E380: At bottom of quickfix stack".to_string()));
+        assert_eq!(title(&lists), "1");
+    }
+}
@@ -0,0 +1,150 @@
+//! Checking a program's output against the expected answer, and the line
+//! diff shown when they differ.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Line for line, ignoring only trailing whitespace and blank lines at
+    /// the end.
+    Exact,
+    /// The same words in the same order, however they are spaced.
+    Tokens,
+    /// Like `Tokens`, but numbers may differ by the absolute or relative
+    /// error given.
+    Float(f64),
+}
+
+impl Mode {
+    /// The mode for the `compare` option, `float_epsilon` applying to
+    /// `float`.
+    pub fn parse(name: &str, epsilon: f64) -> Option<Mode> {
+        match name {
+            "exact" => Some(Mode::Exact),
+            "tokens" => Some(Mode::Tokens),
+            "float" => Some(Mode::Float(epsilon)),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `output` is right, and if not, where it first goes wrong.
+pub fn check(output: &str, expected: &str, mode: Mode) -> Result<(), String> {
+    if mode == Mode::Exact {
+        let lines = |text: &str| -> Vec<String> {
+            let mut lines: Vec<String> = text.lines().map(|l| l.trim_end().to_string()).collect();
+            while lines.last().is_some_and(|l| l.is_empty()) {
+                lines.pop();
+            }
+            lines
+        };
+        let (got, want) = (lines(output), lines(expected));
+        return match (0..got.len().max(want.len())).find(|&i| got.get(i) != want.get(i)) {
+            None => Ok(()),
+            Some(i) => Err(format!("line {}: expected {}, got {}", i + 1, shown(want.get(i)), shown(got.get(i)))),
+        };
+    }
+
+    let (got, want) = (tokens(output), tokens(expected));
+    for i in 0..got.len().max(want.len()) {
+        match (got.get(i), want.get(i)) {
+            (Some(&(line, g)), Some(&(_, w))) if !same_token(g, w, mode) => {
+                return Err(format!("line {}: expected {}, got {}", line, shorten(w), shorten(g)));
+            }
+            (Some(&(line, g)), None) => return Err(format!("line {}: extra output {}", line, shorten(g))),
+            (None, Some(&(line, w))) => return Err(format!("line {}: output ended, expected {}", line, shorten(w))),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The words of `text` with their one-based line numbers.
+fn tokens(text: &str) -> Vec<(usize, &str)> {
+    text.lines().enumerate().flat_map(|(i, line)| line.split_whitespace().map(move |t| (i + 1, t))).collect()
+}
+
+fn same_token(got: &str, want: &str, mode: Mode) -> bool {
+    if got == want {
+        return true;
+    }
+    let Mode::Float(epsilon) = mode else {
+        return false;
+    };
+    match (got.parse::<f64>(), want.parse::<f64>()) {
+        (Ok(g), Ok(w)) if g.is_finite() && w.is_finite() => {
+            let error = (g - w).abs();
+            error <= epsilon || error <= epsilon * w.abs()
+        }
+        _ => false,
+    }
+}
+
+fn shown(line: Option<&String>) -> String {
+    match line {
+        Some(line) => shorten(line),
+        None => "end of output".to_string(),
+    }
+}
+
+fn shorten(text: &str) -> String {
+    const LONGEST: usize = 40;
+    if text.chars().count() <= LONGEST {
+        format!("'{}'", text)
+    } else {
+        format!("'{}...'", text.chars().take(LONGEST).collect::<String>())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Diff {
+    Same(String),
+    /// Only in the expected answer.
+    Expected(String),
+    /// Only in the output.
+    Got(String),
+}
+
+/// The lines of `expected` and `output`, those only in one of them marked,
+/// as the shortest edit between them. Trailing whitespace is ignored.
+pub fn diff(expected: &str, output: &str) -> Vec<Diff> {
+    let want: Vec<&str> = expected.lines().map(str::trim_end).collect();
+    let got: Vec<&str> = output.lines().map(str::trim_end).collect();
+    // Longest common subsequence; past this size, line by line will do.
+    const CELLS: usize = 4_000_000;
+    if want.len() * got.len() > CELLS {
+        let mut lines = Vec::new();
+        for i in 0..want.len().max(got.len()) {
+            match (want.get(i), got.get(i)) {
+                (Some(w), Some(g)) if w == g => lines.push(Diff::Same(w.to_string())),
+                (w, g) => {
+                    lines.extend(w.map(|w| Diff::Expected(w.to_string())));
+                    lines.extend(g.map(|g| Diff::Got(g.to_string())));
+                }
+            }
+        }
+        return lines;
+    }
+
+    // common[i][j]: common lines of want[i..] and got[j..].
+    let mut common = vec![vec![0usize; got.len() + 1]; want.len() + 1];
+    for i in (0..want.len()).rev() {
+        for j in (0..got.len()).rev() {
+            common[i][j] = if want[i] == got[j] { common[i + 1][j + 1] + 1 } else { common[i + 1][j].max(common[i][j + 1]) };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < want.len() || j < got.len() {
+        if i < want.len() && j < got.len() && want[i] == got[j] {
+            lines.push(Diff::Same(want[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if i < want.len() && (j == got.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(Diff::Expected(want[i].to_string()));
+            i += 1;
+        } else {
+            lines.push(Diff::Got(got[j].to_string()));
+            j += 1;
+        }
+    }
+    lines
+}
@@ -0,0 +1,576 @@
+//! `:Run`: the built program against the problem's tests, each judged
+//! against its expected output, in the background. A testlib checker or
+//! interactor beside the solution takes part if there is one.
+
+pub mod compare;
+pub mod process;
+pub mod store;
+pub mod stress;
+#[cfg(test)]
+mod tests;
+
+use crate::editor::{make, quickfix};
+use compare::{Diff, Mode};
+use process::{Execution, Limits, Status};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Checkers, interactors, generators and brute forces get this many times
+/// the solution's time limit.
+pub const HELPER_SLOWDOWN: u32 = 10;
+
+/// One input to run the program on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Case {
+    pub name: String,
+    pub input: PathBuf,
+    /// The expected output, if there is one.
+    pub answer: Option<PathBuf>,
+}
+
+/// The tests for `source`: those enabled in its store, then every `.in`
+/// file beside it named after it (`a.in`, `a1.in`, `a_2.in` for `a.cpp`),
+/// or if there are none of either, `input.txt`. The expected output is in
+/// the `.out` or `.ans` file of the same name, or `output.txt`.
+pub fn find_cases(source: &Path) -> Vec<Case> {
+    let stored = store::Store::for_source(source).tests().into_iter().filter(|t| t.enabled).map(|test| Case {
+        name: format!("#{:02}", test.number),
+        input: test.input,
+        // An empty one is an answer not written yet.
+        answer: Some(test.answer).filter(|p| fs::metadata(p).is_ok_and(|m| m.len() > 0)),
+    });
+    let directory = source.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let stem = source.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned());
+    let mut cases: Vec<Case> = fs::read_dir(directory)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == "in"))
+        .filter(|path| {
+            let name = path.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned());
+            name.strip_prefix(&stem).is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphabetic()))
+        })
+        .map(|input| {
+            let answer = ["out", "ans"].iter().map(|e| input.with_extension(e)).find(|p| p.is_file());
+            let name = input.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned());
+            Case { name, input, answer }
+        })
+        .collect();
+    // Numbered tests in order: a2 before a10.
+    cases.sort_by(|a, b| (a.name.len(), &a.name).cmp(&(b.name.len(), &b.name)));
+    cases.splice(0..0, stored);
+
+    let input = directory.join("input.txt");
+    if cases.is_empty() && input.is_file() {
+        let answer = Some(directory.join("output.txt")).filter(|p| p.is_file());
+        cases.push(Case { name: "input.txt".to_string(), input, answer });
+    }
+    cases
+}
+
+/// The source of the problem's `role` program beside `source`, such as
+/// `a.checker.cpp` for `a.cpp`, or else `checker.cpp`.
+pub fn find_helper(source: &Path, role: &str) -> Option<PathBuf> {
+    let stem = source.file_stem()?.to_string_lossy();
+    let extension = source.extension()?.to_string_lossy();
+    [format!("{}.{}.{}", stem, role, extension), format!("{}.{}", role, extension)]
+        .into_iter()
+        .map(|name| source.with_file_name(name))
+        .find(|path| path.is_file())
+}
+
+/// A program built from its source like the solution is: a checker, an
+/// interactor, or one of those `:Stress` runs.
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub source: PathBuf,
+    /// `makeprg` for it.
+    pub build_command: String,
+    /// `run_command` for it.
+    pub command: String,
+}
+
+impl Program {
+    pub fn new(source: PathBuf, makeprg: &str, run_command: &str) -> Program {
+        let name = source.to_string_lossy().into_owned();
+        Program { build_command: make::expand(makeprg, &name), command: make::expand(run_command, &name), source }
+    }
+
+    /// Builds it unless it is up to date.
+    pub fn build(&self) -> Result<(), String> {
+        if !self.source.is_file() {
+            return Err(format!("{} not found", self.source.display()));
+        }
+        if is_built(&self.source) {
+            return Ok(());
+        }
+        let limits = Limits::time(Duration::from_secs(120));
+        let execution = process::execute(&self.build_command, b"", &limits).map_err(|e| e.to_string())?;
+        if execution.status == Status::Exited(0) {
+            return Ok(());
+        }
+        let entries = quickfix::parse_gcc(&execution.stderr);
+        let first = entries.iter().find(|e| e.is_error()).map(quickfix::describe);
+        let first = first.or_else(|| execution.stderr.lines().next().map(str::to_string)).unwrap_or_default();
+        Err(format!("{} didn't build: {}", self.source.display(), first))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    Accepted,
+    WrongAnswer,
+    RuntimeError,
+    TimeLimitExceeded,
+    MemoryLimitExceeded,
+    OutputLimitExceeded,
+    /// Ran without error, with no answer to compare against.
+    Finished,
+    /// The checker or interactor failed, or couldn't be built.
+    CheckerFailed,
+}
+
+impl Verdict {
+    pub fn label(self) -> &'static str {
+        match self {
+            Verdict::Accepted => "AC",
+            Verdict::WrongAnswer => "WA",
+            Verdict::RuntimeError => "RE",
+            Verdict::TimeLimitExceeded => "TLE",
+            Verdict::MemoryLimitExceeded => "MLE",
+            Verdict::OutputLimitExceeded => "OLE",
+            Verdict::Finished => "OK",
+            Verdict::CheckerFailed => "FAIL",
+        }
+    }
+
+    pub fn is_failure(self) -> bool {
+        !matches!(self, Verdict::Accepted | Verdict::Finished)
+    }
+
+    /// Whether the program went over one of its limits.
+    pub fn is_over_limit(self) -> bool {
+        matches!(self, Verdict::TimeLimitExceeded | Verdict::MemoryLimitExceeded | Verdict::OutputLimitExceeded)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    pub verdict: Verdict,
+    /// CPU time.
+    pub time: Duration,
+    pub wall_time: Duration,
+    pub memory_kb: u64,
+    /// Why it failed: the first difference, the exit code or the signal.
+    pub detail: String,
+    pub output: String,
+    pub stderr: String,
+    /// What went between the program and the interactor, if there was one.
+    pub transcript: Vec<String>,
+}
+
+/// How to run and judge a test.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Shell command that starts the program.
+    pub command: String,
+    pub limits: Limits,
+    pub mode: Mode,
+    /// Judges the output in place of `mode`, run as `checker input output
+    /// answer` and saying how it went with its exit code, as with testlib.
+    pub checker: Option<Program>,
+    /// Talks with the program in place of its input, run as `interactor
+    /// input output answer`, with the output going to the checker if any.
+    pub interactor: Option<Program>,
+}
+
+impl Settings {
+    /// Builds the checker and the interactor if they need it.
+    pub fn prepare(&self) -> Result<(), String> {
+        self.checker.iter().chain(&self.interactor).try_for_each(Program::build)
+    }
+}
+
+fn failed(detail: String) -> Outcome {
+    Outcome {
+        verdict: Verdict::RuntimeError,
+        time: Duration::ZERO,
+        wall_time: Duration::ZERO,
+        memory_kb: 0,
+        detail,
+        output: String::new(),
+        stderr: String::new(),
+        transcript: Vec::new(),
+    }
+}
+
+/// Runs the program on `case` and judges what it did. With an interactor,
+/// the transcript is also written beside the input, as `NN.log` for
+/// `NN.in`.
+pub fn judge(case: &Case, settings: &Settings) -> Outcome {
+    let outcome = match fs::read(&case.input) {
+        Ok(input) => {
+            let answer = case.answer.as_ref().and_then(|path| fs::read_to_string(path).ok());
+            judge_input(&input, answer.as_deref(), settings)
+        }
+        Err(e) => failed(format!("{}: {}", case.input.display(), e)),
+    };
+    if !outcome.transcript.is_empty() {
+        fs::write(case.input.with_extension("log"), outcome.transcript.join("\n") + "\n").ok();
+    }
+    outcome
+}
+
+/// Runs the program on `input` and judges its output against `answer`.
+pub fn judge_input(input: &[u8], answer: Option<&str>, settings: &Settings) -> Outcome {
+    if settings.checker.is_some() || settings.interactor.is_some() {
+        return judge_with_helpers(input, answer.unwrap_or(""), settings)
+            .unwrap_or_else(|detail| Outcome { verdict: Verdict::CheckerFailed, ..failed(detail) });
+    }
+    let execution = match process::execute(&settings.command, input, &settings.limits) {
+        Ok(execution) => execution,
+        Err(e) => return failed(format!("{}: {}", settings.command, e)),
+    };
+    let (verdict, detail) = match (run_failure(&execution, settings), answer) {
+        (Some(failure), _) => failure,
+        (None, None) => (Verdict::Finished, String::new()),
+        (None, Some(answer)) => match compare::check(&execution.stdout, answer, settings.mode) {
+            Ok(()) => (Verdict::Accepted, String::new()),
+            Err(detail) => (Verdict::WrongAnswer, detail),
+        },
+    };
+    outcome(verdict, detail, execution, Vec::new())
+}
+
+/// Judges with the checker or through the interactor, which get the
+/// input, output and answer as files.
+fn judge_with_helpers(input: &[u8], answer: &str, settings: &Settings) -> Result<Outcome, String> {
+    let files = Files::new()?;
+    let paths = [files.write("input", input.to_vec())?, files.dir.join("output"), files.write("answer", answer.into())?];
+    let arguments: Vec<String> = paths.iter().map(|p| make::shell_quote(&p.to_string_lossy())).collect();
+    let helper = |program: &Program| format!("{} {}", program.command, arguments.join(" "));
+    let limits = Limits::time(settings.limits.wall_time * HELPER_SLOWDOWN);
+
+    let (execution, transcript, said) = match &settings.interactor {
+        Some(interactor) => {
+            let interaction = process::interact(&settings.command, &helper(interactor), &settings.limits, &limits)
+                .map_err(|e| format!("{}: {}", settings.command, e))?;
+            let said = helper_verdict(&interaction.interactor, "interactor", &limits);
+            (interaction.program, interaction.transcript, Some(said))
+        }
+        None => {
+            let execution = process::execute(&settings.command, input, &settings.limits)
+                .map_err(|e| format!("{}: {}", settings.command, e))?;
+            fs::write(&paths[1], &execution.stdout).map_err(|e| format!("{}: {}", paths[1].display(), e))?;
+            (execution, Vec::new(), None)
+        }
+    };
+    // A program the interactor has given up on often fails too, for want
+    // of input, but the interactor says why it went wrong.
+    let (verdict, detail) = match (run_failure(&execution, settings), said) {
+        (Some(failure), _) if failure.0.is_over_limit() => failure,
+        (_, Some(said)) if said.0 != Verdict::Accepted => said,
+        (Some(failure), _) => failure,
+        (None, Some(said)) if settings.checker.is_none() => said,
+        (None, _) => match &settings.checker {
+            Some(checker) => {
+                let checked = process::execute(&helper(checker), b"", &limits).map_err(|e| format!("{}: {}", checker.command, e))?;
+                helper_verdict(&checked, "checker", &limits)
+            }
+            None => (Verdict::Accepted, String::new()),
+        },
+    };
+    Ok(outcome(verdict, detail, execution, transcript))
+}
+
+/// Why the program's run failed, if it did. With the address space
+/// limited, running out of memory shows as an allocation failing, not as
+/// a signal, so it is only told apart from other crashes by the message
+/// that leaves on standard error, as C++ does for `std::bad_alloc`. A
+/// program that ignores a failed `malloc` gets a runtime error instead.
+fn run_failure(execution: &Execution, settings: &Settings) -> Option<(Verdict, String)> {
+    let limits = &settings.limits;
+    let out_of_memory = execution.status != Status::Exited(0)
+        && ["std::bad_alloc", "Cannot allocate memory"].iter().any(|s| execution.stderr.contains(s));
+    match execution.status {
+        Status::TimedOut => Some((Verdict::TimeLimitExceeded, format!("killed after {} ms on the clock", limits.wall_time.as_millis()))),
+        Status::OutputLimitExceeded | Status::Signaled(libc::SIGXFSZ) => {
+            Some((Verdict::OutputLimitExceeded, format!("output limit {}", format_memory(limits.output / 1024))))
+        }
+        _ if execution.cpu_time > limits.time || execution.status == Status::Signaled(libc::SIGXCPU) => {
+            Some((Verdict::TimeLimitExceeded, format!("CPU time limit {} ms", limits.time.as_millis())))
+        }
+        _ if limits.memory > 0 && out_of_memory => {
+            Some((Verdict::MemoryLimitExceeded, format!("memory limit {}", format_memory(limits.memory / 1024))))
+        }
+        Status::Signaled(signal) => Some((Verdict::RuntimeError, process::signal_name(signal))),
+        // `sh` couldn't find or run the program.
+        Status::Exited(code @ (126 | 127)) => {
+            Some((Verdict::RuntimeError, execution.stderr.lines().next().map_or(format!("exit code {}", code), str::to_string)))
+        }
+        Status::Exited(0) => None,
+        Status::Exited(code) => Some((Verdict::RuntimeError, format!("exit code {}", code))),
+    }
+}
+
+/// What a testlib checker or interactor made of the output: exit code 0
+/// accepts it, 1 and 2 reject it and anything else is its own failure.
+/// Its comment is the first line it wrote to standard error.
+fn helper_verdict(execution: &Execution, name: &str, limits: &Limits) -> (Verdict, String) {
+    let comment = execution.stderr.lines().next().unwrap_or("").trim().to_string();
+    match execution.status {
+        Status::Exited(0) => (Verdict::Accepted, comment),
+        Status::Exited(1 | 2) => (Verdict::WrongAnswer, comment),
+        Status::Exited(code) if comment.is_empty() => (Verdict::CheckerFailed, format!("{} exited with code {}", name, code)),
+        Status::Exited(_) => (Verdict::CheckerFailed, comment),
+        Status::Signaled(signal) => (Verdict::CheckerFailed, format!("{} died of {}", name, process::signal_name(signal))),
+        Status::TimedOut => (Verdict::CheckerFailed, format!("{} took over {} ms", name, limits.wall_time.as_millis())),
+        Status::OutputLimitExceeded => (Verdict::CheckerFailed, format!("{} wrote too much", name)),
+    }
+}
+
+fn outcome(verdict: Verdict, detail: String, execution: Execution, transcript: Vec<String>) -> Outcome {
+    Outcome {
+        verdict,
+        time: execution.cpu_time,
+        wall_time: execution.time,
+        memory_kb: execution.memory_kb,
+        detail,
+        output: execution.stdout,
+        stderr: execution.stderr,
+        transcript,
+    }
+}
+
+/// A directory for the files a checker or interactor reads, removed with
+/// them when dropped.
+struct Files {
+    dir: PathBuf,
+}
+
+impl Files {
+    fn new() -> Result<Files, String> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("vimr-judge-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+        let dir = std::env::temp_dir().join(name);
+        fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        Ok(Files { dir })
+    }
+
+    fn write(&self, name: &str, contents: Vec<u8>) -> Result<PathBuf, String> {
+        let path = self.dir.join(name);
+        fs::write(&path, contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(path)
+    }
+}
+
+impl Drop for Files {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+/// The tests being run, one after another on another thread, and what
+/// has come of those finished so far.
+pub struct Session {
+    pub cases: Vec<Case>,
+    pub outcomes: Vec<Option<Outcome>>,
+    // In a mutex only so the editor view holding the session is `Sync`.
+    results: Mutex<Receiver<(usize, Outcome)>>,
+}
+
+impl Session {
+    pub fn start(cases: Vec<Case>, settings: Settings) -> Session {
+        let (sender, results) = mpsc::channel();
+        let to_run = cases.clone();
+        thread::spawn(move || {
+            if let Err(e) = settings.prepare() {
+                for i in 0..to_run.len() {
+                    sender.send((i, Outcome { verdict: Verdict::CheckerFailed, ..failed(e.clone()) })).ok();
+                }
+                return;
+            }
+            for (i, case) in to_run.iter().enumerate() {
+                if sender.send((i, judge(case, &settings))).is_err() {
+                    break;
+                }
+            }
+        });
+        let outcomes = vec![None; cases.len()];
+        Session { cases, outcomes, results: Mutex::new(results) }
+    }
+
+    /// Takes in the results that have come. Returns whether there were any.
+    pub fn poll(&mut self) -> bool {
+        let Ok(results) = self.results.get_mut() else {
+            return false;
+        };
+        let mut any = false;
+        while let Ok((i, outcome)) = results.try_recv() {
+            self.outcomes[i] = Some(outcome);
+            any = true;
+        }
+        any
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.outcomes.iter().all(Option::is_some)
+    }
+
+    /// `3 tests: 2 AC, 1 WA`, counting those finished.
+    pub fn summary(&self) -> String {
+        let mut counts: Vec<(&str, usize)> = Vec::new();
+        for outcome in self.outcomes.iter().flatten() {
+            let label = outcome.verdict.label();
+            match counts.iter_mut().find(|(l, _)| *l == label) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((label, 1)),
+            }
+        }
+        let counts: Vec<String> = counts.iter().map(|(label, count)| format!("{} {}", count, label)).collect();
+        let tests = self.cases.len();
+        format!("{} test{}: {}", tests, if tests == 1 { "" } else { "s" }, counts.join(", "))
+    }
+
+    /// A line per test for the verdict table.
+    pub fn table(&self) -> Vec<String> {
+        let width = self.cases.iter().map(|c| c.name.chars().count()).max().unwrap_or(0);
+        self.cases
+            .iter()
+            .zip(&self.outcomes)
+            .map(|(case, outcome)| match outcome {
+                None => format!("{:<width$}  ...", case.name),
+                Some(outcome) => format!(
+                    "{:<width$}  {:<4}  {:>6} ms  {:>7}  {}",
+                    case.name,
+                    outcome.verdict.label(),
+                    outcome.time.as_millis(),
+                    format_memory(outcome.memory_kb),
+                    outcome.detail
+                ),
+            })
+            .collect()
+    }
+
+    /// What the diff view shows for test `index`: its input, the output
+    /// against the expected answer, and anything on standard error.
+    pub fn report(&self, index: usize) -> Vec<String> {
+        let (Some(case), Some(Some(outcome))) = (self.cases.get(index), self.outcomes.get(index)) else {
+            return Vec::new();
+        };
+        let input = fs::read_to_string(&case.input).unwrap_or_default();
+        let answer = case.answer.as_ref().and_then(|path| fs::read_to_string(path).ok());
+        report(&case.name, &format!("input ({})", case.input.display()), &input, answer.as_deref(), outcome)
+    }
+}
+
+/// Lines showing how a test went: `input` under the `input_title`, the
+/// output diffed against `answer` if it was wrong (or what went between
+/// the program and the interactor), and standard error.
+pub fn report(name: &str, input_title: &str, input: &str, answer: Option<&str>, outcome: &Outcome) -> Vec<String> {
+    let mut lines = vec![format!("{}: {} {}", name, outcome.verdict.label(), outcome.detail).trim_end().to_string()];
+    lines.push(format!(
+        "{} ms of CPU time, {} ms on the clock, {} of memory",
+        outcome.time.as_millis(),
+        outcome.wall_time.as_millis(),
+        format_memory(outcome.memory_kb)
+    ));
+    lines.push(String::new());
+    lines.push(format!("--- {}", input_title));
+    excerpt(&mut lines, input.lines().map(str::to_string));
+
+    lines.push(String::new());
+    if !outcome.transcript.is_empty() {
+        lines.push("--- transcript (> from the program, < from the interactor)".to_string());
+        excerpt(&mut lines, outcome.transcript.iter().cloned());
+    } else if let Some(answer) = answer.filter(|_| outcome.verdict != Verdict::Accepted) {
+        lines.push("--- output (- expected, + got)".to_string());
+        excerpt(&mut lines, collapse(compare::diff(answer, &outcome.output)).into_iter());
+    } else {
+        lines.push("--- output".to_string());
+        excerpt(&mut lines, outcome.output.lines().map(str::to_string));
+    }
+    if !outcome.stderr.is_empty() {
+        lines.push(String::new());
+        lines.push("--- stderr".to_string());
+        excerpt(&mut lines, outcome.stderr.lines().map(str::to_string));
+    }
+    lines
+}
+
+/// The diff as `  same`, `- expected` and `+ got` lines, long runs of
+/// matching lines cut down to the three next to each difference.
+fn collapse(diff: Vec<Diff>) -> Vec<String> {
+    const CONTEXT: usize = 3;
+    // Matching lines go here until the next difference, or the end.
+    fn flush(lines: &mut Vec<String>, same: &mut Vec<String>, before: usize, after: usize) {
+        if same.len() > before + after + 1 {
+            let hidden = same.len() - before - after;
+            lines.extend(same[..before].iter().map(|t| format!("  {}", t)));
+            lines.push(format!("  [{} matching lines]", hidden));
+            lines.extend(same[before + hidden..].iter().map(|t| format!("  {}", t)));
+        } else {
+            lines.extend(same.iter().map(|t| format!("  {}", t)));
+        }
+        same.clear();
+    }
+
+    let mut lines = Vec::new();
+    let mut same = Vec::new();
+    let mut first = true;
+    for line in diff {
+        let changed = match line {
+            Diff::Same(text) => {
+                same.push(text);
+                continue;
+            }
+            Diff::Expected(text) => format!("- {}", text),
+            Diff::Got(text) => format!("+ {}", text),
+        };
+        flush(&mut lines, &mut same, if first { 0 } else { CONTEXT }, CONTEXT);
+        first = false;
+        lines.push(changed);
+    }
+    flush(&mut lines, &mut same, if first { 0 } else { CONTEXT }, 0);
+    lines
+}
+
+/// Adds the first lines of `text`, noting how many more there were.
+fn excerpt(lines: &mut Vec<String>, text: impl Iterator<Item = String>) {
+    const SHOWN: usize = 200;
+    let mut hidden = 0;
+    for (i, line) in text.enumerate() {
+        if i < SHOWN {
+            lines.push(line);
+        } else {
+            hidden += 1;
+        }
+    }
+    if hidden > 0 {
+        lines.push(format!("[{} more lines]", hidden));
+    }
+}
+
+/// Whether the program built from `source` (named like it, without the
+/// extension) is there and newer than it.
+pub fn is_built(source: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(&source.with_extension("")), modified(source)) {
+        (Some(binary), Some(source)) => binary >= source,
+        _ => false,
+    }
+}
+
+/// `812 KB` or `3.4 MB`.
+pub fn format_memory(kb: u64) -> String {
+    if kb < 1024 {
+        format!("{} KB", kb)
+    } else {
+        format!("{:.1} MB", kb as f64 / 1024.0)
+    }
+}
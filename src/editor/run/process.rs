@@ -0,0 +1,349 @@
+//! Running a program on some input, or against an interactor, within
+//! limits on its time, memory and output, while measuring what it used.
+
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Exited(i32),
+    /// Killed by the signal.
+    Signaled(i32),
+    /// Killed for running past the wall time limit.
+    TimedOut,
+    /// Killed for writing more than the output limit.
+    OutputLimitExceeded,
+}
+
+/// What a program may use, enforced with rlimits where the kernel can.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// CPU time. The kernel stops the program once it has used this,
+    /// rounded up to a second.
+    pub time: Duration,
+    /// Time on the clock, for programs that wait rather than compute.
+    pub wall_time: Duration,
+    /// Bytes of address space, which the stack may take all of; 0 for no
+    /// limit.
+    pub memory: u64,
+    /// Bytes it may write to standard output or error, or to any file; 0
+    /// for no limit.
+    pub output: u64,
+}
+
+impl Limits {
+    /// Only `time`, on the clock and of CPU alike.
+    pub fn time(time: Duration) -> Limits {
+        Limits { time, wall_time: time, memory: 0, output: 0 }
+    }
+}
+
+pub struct Execution {
+    pub status: Status,
+    /// Wall-clock time.
+    pub time: Duration,
+    /// User and system CPU time.
+    pub cpu_time: Duration,
+    /// Peak resident memory in kilobytes.
+    pub memory_kb: u64,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Starts `command` with `sh`, which `exec`s it so the measurements are
+/// of the program itself, feeds it `input` and waits for it to finish
+/// within `limits`.
+pub fn execute(command: &str, input: &[u8], limits: &Limits) -> io::Result<Execution> {
+    let mut child = spawn(command, limits)?;
+    let started = Instant::now();
+
+    // Fed from another thread so a program that writes before it has read
+    // everything can't block on a full pipe.
+    if let Some(mut stdin) = child.stdin.take() {
+        let input = input.to_vec();
+        thread::spawn(move || stdin.write_all(&input));
+    }
+    let flooded = Arc::new(AtomicBool::new(false));
+    let stdout = read_all(child.stdout.take(), limits.output, flooded.clone());
+    let stderr = read_all(child.stderr.take(), limits.output, flooded.clone());
+
+    let (status, usage) = wait(&mut child, started, limits.wall_time, &flooded)?;
+    Ok(execution(status, usage, started.elapsed(), stdout, stderr, &flooded))
+}
+
+/// What came of running a program against an interactor.
+pub struct Interaction {
+    /// The program's run; its `stdout` is all it wrote to the interactor.
+    pub program: Execution,
+    /// Likewise, the interactor's.
+    pub interactor: Execution,
+    /// What went between them line by line, `> ` before the program's
+    /// lines and `< ` before the interactor's.
+    pub transcript: Vec<String>,
+}
+
+/// Starts `command` and `interactor`, each reading what the other writes,
+/// and waits for them to finish within their limits.
+pub fn interact(command: &str, interactor: &str, limits: &Limits, interactor_limits: &Limits) -> io::Result<Interaction> {
+    let mut program = spawn(command, limits)?;
+    let mut other = match spawn(interactor, interactor_limits) {
+        Ok(other) => other,
+        Err(e) => {
+            program.kill().ok();
+            program.wait().ok();
+            return Err(e);
+        }
+    };
+    let started = Instant::now();
+
+    let transcript = Arc::new(Mutex::new(Vec::new()));
+    let (flooded, other_flooded) = (Arc::new(AtomicBool::new(false)), Arc::new(AtomicBool::new(false)));
+    let sent = relay(program.stdout.take(), other.stdin.take(), "> ", transcript.clone(), limits.output, flooded.clone());
+    let received = relay(
+        other.stdout.take(),
+        program.stdin.take(),
+        "< ",
+        transcript.clone(),
+        interactor_limits.output,
+        other_flooded.clone(),
+    );
+    let program_errors = read_all(program.stderr.take(), limits.output, flooded.clone());
+    let other_errors = read_all(other.stderr.take(), interactor_limits.output, other_flooded.clone());
+
+    let (wall_time, other_flag) = (interactor_limits.wall_time, other_flooded.clone());
+    let waiting = thread::spawn(move || {
+        let (status, usage) = wait(&mut other, started, wall_time, &other_flag)?;
+        Ok((status, usage, started.elapsed()))
+    });
+    let (status, usage) = wait(&mut program, started, limits.wall_time, &flooded)?;
+    let time = started.elapsed();
+    let (other_status, other_usage, other_time) =
+        waiting.join().unwrap_or_else(|_| Err(io::Error::other("waiting for the interactor failed")))?;
+
+    let program = execution(status, usage, time, sent, program_errors, &flooded);
+    let interactor = execution(other_status, other_usage, other_time, received, other_errors, &other_flooded);
+    let transcript = mem::take(&mut *transcript.lock().unwrap_or_else(|e| e.into_inner()));
+    Ok(Interaction { program, interactor, transcript })
+}
+
+/// `sh` running `command` within `limits`, with pipes for all of its
+/// standard streams.
+fn spawn(command: &str, limits: &Limits) -> io::Result<Child> {
+    let limits = *limits;
+    let mut shell = Command::new("sh");
+    shell
+        .arg("-c")
+        .arg(format!("exec {}", command))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // A group of its own, killed as one with anything it starts.
+        .process_group(0);
+    // SAFETY: between fork and exec the child only calls getrlimit and
+    // setrlimit, which are async-signal-safe, and allocates nothing.
+    unsafe { shell.pre_exec(move || set_limits(&limits)) };
+    shell.spawn()
+}
+
+/// Applies `limits` to this process, which passes them on to the program
+/// it `exec`s.
+fn set_limits(limits: &Limits) -> io::Result<()> {
+    let set = |resource, soft: u64, hard: u64| {
+        // SAFETY: rlimit is plain data, for which all zeroes is valid.
+        let mut limit: libc::rlimit = unsafe { mem::zeroed() };
+        // SAFETY: `limit` is a local that outlives the calls.
+        unsafe {
+            if libc::getrlimit(resource, &mut limit) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // Only root can go above the hard limit.
+            let hard = (hard as libc::rlim_t).min(limit.rlim_max);
+            limit = libc::rlimit { rlim_cur: (soft as libc::rlim_t).min(hard), rlim_max: hard };
+            if libc::setrlimit(resource, &limit) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    };
+    set(libc::RLIMIT_CORE, 0, 0)?;
+    // SIGXCPU once over, then a second later SIGKILL.
+    let seconds = limits.time.as_secs_f64().ceil() as u64;
+    set(libc::RLIMIT_CPU, seconds, seconds + 1)?;
+    if limits.memory > 0 {
+        set(libc::RLIMIT_AS, limits.memory, limits.memory)?;
+        set(libc::RLIMIT_STACK, limits.memory, limits.memory)?;
+    }
+    if limits.output > 0 {
+        set(libc::RLIMIT_FSIZE, limits.output, limits.output)?;
+    }
+    Ok(())
+}
+
+fn execution(
+    status: Status,
+    usage: libc::rusage,
+    time: Duration,
+    stdout: JoinHandle<Vec<u8>>,
+    stderr: JoinHandle<Vec<u8>>,
+    flooded: &AtomicBool,
+) -> Execution {
+    let text = |reader: JoinHandle<Vec<u8>>| String::from_utf8_lossy(&reader.join().unwrap_or_default()).into_owned();
+    let (stdout, stderr) = (text(stdout), text(stderr));
+    // Known for sure only once everything it wrote has been read: it may
+    // have finished, or died writing, before the wait noticed.
+    let status = if flooded.load(Ordering::Relaxed) && status != Status::TimedOut { Status::OutputLimitExceeded } else { status };
+    let seconds = |t: libc::timeval| Duration::from_secs(t.tv_sec.max(0) as u64) + Duration::from_micros(t.tv_usec.max(0) as u64);
+    Execution {
+        status,
+        time,
+        cpu_time: seconds(usage.ru_utime) + seconds(usage.ru_stime),
+        // Kilobytes on Linux.
+        memory_kb: usage.ru_maxrss.max(0) as u64,
+        stdout,
+        stderr,
+    }
+}
+
+/// Everything that comes from `pipe`, or at most `limit` bytes of it (if
+/// not 0), `flooded` being set if there was more.
+fn read_all(pipe: Option<impl Read + Send + 'static>, limit: u64, flooded: Arc<AtomicBool>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let Some(pipe) = pipe else {
+            return bytes;
+        };
+        let limit = if limit == 0 { u64::MAX } else { limit };
+        // A byte more tells whether there was more; the pipe is only closed
+        // after `flooded` is set, so the program can't die writing to it
+        // before then.
+        let mut pipe = pipe.take(limit.saturating_add(1));
+        pipe.read_to_end(&mut bytes).ok();
+        if bytes.len() as u64 > limit {
+            bytes.truncate(limit as usize);
+            flooded.store(true, Ordering::Relaxed);
+        }
+        bytes
+    })
+}
+
+/// Passes what comes out of `from` on to `to`, adding it to `transcript`
+/// first, a line (or as much of one as came at once) after `prefix` at a
+/// time. Returns all that came, stopping past `limit` bytes (if not 0) to
+/// set `flooded`.
+fn relay(
+    from: Option<ChildStdout>,
+    mut to: Option<ChildStdin>,
+    prefix: &'static str,
+    transcript: Arc<Mutex<Vec<String>>>,
+    limit: u64,
+    flooded: Arc<AtomicBool>,
+) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut all = Vec::new();
+        let Some(mut from) = from else {
+            return all;
+        };
+        let mut buffer = [0; 4096];
+        loop {
+            let read = match from.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => &buffer[..read],
+            };
+            all.extend_from_slice(read);
+            if limit > 0 && all.len() as u64 > limit {
+                all.truncate(limit as usize);
+                flooded.store(true, Ordering::Relaxed);
+                break;
+            }
+            // Logged before it is passed on, so that any reply comes after
+            // it in the transcript.
+            if let Ok(mut lines) = transcript.lock() {
+                for line in read.split_inclusive(|&b| b == b'\n') {
+                    let line = String::from_utf8_lossy(line);
+                    lines.push(format!("{}{}", prefix, line.trim_end_matches('\n')));
+                }
+            }
+            // Once the other side is gone, reading on keeps this one from
+            // blocking on a full pipe.
+            if to.as_mut().is_some_and(|pipe| pipe.write_all(read).is_err()) {
+                to = None;
+            }
+        }
+        all
+    })
+}
+
+/// Waits for `child` to finish, killing it once `wall_time` has passed
+/// since `started` or once it has `flooded` its output, and then reaps it
+/// with `wait4` for its resource usage.
+fn wait(child: &mut Child, started: Instant, wall_time: Duration, flooded: &AtomicBool) -> io::Result<(Status, libc::rusage)> {
+    let pid = child.id() as libc::pid_t;
+    let mut killed = None;
+    loop {
+        // SAFETY: siginfo_t is plain data, for which all zeroes is valid.
+        let mut info: libc::siginfo_t = unsafe { mem::zeroed() };
+        // WNOWAIT leaves it to be reaped below, so that until its group is
+        // killed no other process can be given its id.
+        let flags = libc::WEXITED | libc::WNOHANG | libc::WNOWAIT;
+        // SAFETY: `pid` is our child, not yet reaped; `info` is a local
+        // that outlives the call.
+        if unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, flags) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: waitid filled `info` in, or left it zeroed if the child
+        // is still running.
+        if unsafe { info.si_pid() } != 0 {
+            break;
+        }
+        if started.elapsed() > wall_time {
+            killed = Some(Status::TimedOut);
+            break;
+        }
+        if flooded.load(Ordering::Relaxed) {
+            killed = Some(Status::OutputLimitExceeded);
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    // Anything it started goes with it, as it may hold its pipes open.
+    // SAFETY: the group is the child's own, and the child isn't reaped.
+    unsafe { libc::kill(-pid, libc::SIGKILL) };
+
+    let mut status = 0;
+    // SAFETY: rusage is plain data, for which all zeroes is valid.
+    let mut usage: libc::rusage = unsafe { mem::zeroed() };
+    // SAFETY: `pid` is our child, not yet reaped; the pointers are to
+    // locals that outlive the call.
+    if unsafe { libc::wait4(pid, &mut status, 0, &mut usage) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let status = killed.unwrap_or(if libc::WIFSIGNALED(status) {
+        Status::Signaled(libc::WTERMSIG(status))
+    } else {
+        Status::Exited(libc::WEXITSTATUS(status))
+    });
+    Ok((status, usage))
+}
+
+/// The usual name of `signal`, such as `SIGSEGV`.
+pub fn signal_name(signal: i32) -> String {
+    let name = match signal {
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGILL => "SIGILL",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        _ => return format!("signal {}", signal),
+    };
+    name.to_string()
+}
@@ -0,0 +1,142 @@
+//! Tests kept with a solution: `.vimr/tests/<name>/NN.in` and `NN.out`
+//! for `<name>.cpp`, next to it. An `NN.off` file leaves test NN out of
+//! runs; `NN.log` is its last transcript with an interactor.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const INPUT_HEADER: &str = "### input";
+const OUTPUT_HEADER: &str = "### output";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Test {
+    pub number: usize,
+    pub enabled: bool,
+    pub input: PathBuf,
+    pub answer: PathBuf,
+}
+
+pub struct Store {
+    pub dir: PathBuf,
+}
+
+impl Store {
+    pub fn for_source(source: &Path) -> Store {
+        let directory = source.parent().unwrap_or(Path::new(""));
+        let stem = source.file_stem().unwrap_or_default();
+        Store { dir: directory.join(".vimr").join("tests").join(stem) }
+    }
+
+    fn path(&self, number: usize, extension: &str) -> PathBuf {
+        self.dir.join(format!("{:02}.{}", number, extension))
+    }
+
+    /// The tests there are, in order.
+    pub fn tests(&self) -> Vec<Test> {
+        let mut numbers: Vec<usize> = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                (path.extension()? == "in").then_some(())?;
+                path.file_stem()?.to_str()?.parse().ok()
+            })
+            .collect();
+        numbers.sort_unstable();
+        numbers
+            .into_iter()
+            .map(|number| Test {
+                number,
+                enabled: !self.path(number, "off").exists(),
+                input: self.path(number, "in"),
+                answer: self.path(number, "out"),
+            })
+            .collect()
+    }
+
+    /// Saves a new test after the last one. Returns its number.
+    pub fn add(&self, input: &str, answer: &str) -> io::Result<usize> {
+        let number = self.tests().last().map_or(1, |t| t.number + 1);
+        self.write(number, input, answer)?;
+        Ok(number)
+    }
+
+    /// The input and expected output of test `number`.
+    pub fn read(&self, number: usize) -> io::Result<(String, String)> {
+        let input = fs::read_to_string(self.path(number, "in"))?;
+        let answer = fs::read_to_string(self.path(number, "out")).unwrap_or_default();
+        Ok((input, answer))
+    }
+
+    pub fn write(&self, number: usize, input: &str, answer: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(number, "in"), input)?;
+        fs::write(self.path(number, "out"), answer)
+    }
+
+    pub fn delete(&self, number: usize) -> io::Result<()> {
+        fs::remove_file(self.path(number, "in"))?;
+        for extension in ["out", "off", "log"] {
+            fs::remove_file(self.path(number, extension)).ok();
+        }
+        Ok(())
+    }
+
+    pub fn set_enabled(&self, number: usize, enabled: bool) -> io::Result<()> {
+        if !self.path(number, "in").exists() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("no test {}", number)));
+        }
+        let marker = self.path(number, "off");
+        if enabled {
+            fs::remove_file(marker).or_else(|e| if e.kind() == io::ErrorKind::NotFound { Ok(()) } else { Err(e) })
+        } else {
+            fs::write(marker, "")
+        }
+    }
+
+    /// Numbers the tests 1, 2, 3... in their current order, closing gaps
+    /// left by deleted ones. Returns how many there are.
+    pub fn renumber(&self) -> io::Result<usize> {
+        let tests = self.tests();
+        // Numbers only go down, so renaming in order never overwrites.
+        for (i, test) in tests.iter().enumerate() {
+            let number = i + 1;
+            if test.number == number {
+                continue;
+            }
+            for extension in ["in", "out", "off", "log"] {
+                let from = self.path(test.number, extension);
+                if from.exists() {
+                    fs::rename(from, self.path(number, extension))?;
+                }
+            }
+        }
+        Ok(tests.len())
+    }
+}
+
+/// A test as one text to edit: its input under `### input`, then its
+/// expected output under `### output`.
+pub fn to_text(input: &str, answer: &str) -> String {
+    format!("{}\n{}{}\n{}", INPUT_HEADER, with_newline(input), OUTPUT_HEADER, with_newline(answer))
+}
+
+/// The input and expected output in text made by `to_text`.
+pub fn parse_text(text: &str) -> Result<(String, String), String> {
+    let rest = text.strip_prefix(INPUT_HEADER).ok_or_else(|| format!("the first line must be {}", INPUT_HEADER))?;
+    let rest = rest.strip_prefix('\n').unwrap_or(rest);
+    let split = if rest.starts_with(OUTPUT_HEADER) { Some(0) } else { rest.find(&format!("\n{}", OUTPUT_HEADER)).map(|i| i + 1) };
+    let split = split.ok_or_else(|| format!("missing the {} line", OUTPUT_HEADER))?;
+    let answer = &rest[split + OUTPUT_HEADER.len()..];
+    let answer = answer.strip_prefix('\n').unwrap_or(answer);
+    Ok((rest[..split].to_string(), with_newline(answer)))
+}
+
+fn with_newline(text: &str) -> String {
+    if text.is_empty() || text.ends_with('\n') {
+        text.to_string()
+    } else {
+        format!("{}\n", text)
+    }
+}
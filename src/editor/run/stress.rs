@@ -0,0 +1,126 @@
+//! `:Stress`: random tests from a generator, the solution's output checked
+//! against a brute force one's, until they disagree.
+
+use super::process::{self, Limits, Status};
+use super::{judge_input, Outcome, Program, Settings, Verdict, HELPER_SLOWDOWN};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What to stress test and for how long.
+#[derive(Clone, Debug)]
+pub struct Plan {
+    pub solution: PathBuf,
+    /// Prints a test for the seed it gets as its argument.
+    pub generator: PathBuf,
+    /// A slow but surely right solution.
+    pub brute: PathBuf,
+    /// How the three are built and started, as in `makeprg` and
+    /// `run_command`.
+    pub makeprg: String,
+    pub run_command: String,
+    pub iterations: usize,
+    pub budget: Duration,
+    /// Judging the solution; the generator and the brute force get
+    /// `HELPER_SLOWDOWN` times its time limit.
+    pub settings: Settings,
+}
+
+/// The first seed on which the solution went wrong.
+#[derive(Clone, Debug)]
+pub struct Failure {
+    pub seed: u64,
+    pub input: String,
+    /// The brute force's output.
+    pub answer: String,
+    pub outcome: Outcome,
+}
+
+#[derive(Clone, Debug)]
+pub enum Report {
+    /// That many tests have passed.
+    Passed(usize),
+    Failed(Failure),
+    /// All tests, or as many as there was time for, passed.
+    Finished(usize),
+    /// A program didn't build or failed on its own.
+    Error(String),
+}
+
+pub struct Stress {
+    pub started: Instant,
+    stop: Arc<AtomicBool>,
+    // In a mutex only so the editor view holding it is `Sync`.
+    reports: Mutex<Receiver<Report>>,
+}
+
+impl Stress {
+    /// Builds the programs and starts testing on another thread.
+    pub fn start(plan: Plan) -> Stress {
+        let (sender, reports) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            let report = run(&plan, &sender, &stopped).unwrap_or_else(Report::Error);
+            sender.send(report).ok();
+        });
+        Stress { started: Instant::now(), stop, reports: Mutex::new(reports) }
+    }
+
+    /// Stops after the test being run.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// The latest report, if any came.
+    pub fn poll(&mut self) -> Option<Report> {
+        let reports = self.reports.get_mut().ok()?;
+        let mut latest = None;
+        while let Ok(report) = reports.try_recv() {
+            latest = Some(report);
+        }
+        latest
+    }
+}
+
+fn run(plan: &Plan, sender: &Sender<Report>, stop: &AtomicBool) -> Result<Report, String> {
+    let mut commands = Vec::new();
+    for source in [&plan.generator, &plan.brute, &plan.solution] {
+        let program = Program::new(source.clone(), &plan.makeprg, &plan.run_command);
+        program.build()?;
+        commands.push(program.command);
+    }
+    plan.settings.prepare()?;
+    let slow = Limits::time(plan.settings.limits.wall_time * HELPER_SLOWDOWN);
+    let started = Instant::now();
+    for seed in 1..=plan.iterations as u64 {
+        if stop.load(Ordering::Relaxed) || started.elapsed() > plan.budget {
+            return Ok(Report::Finished(seed as usize - 1));
+        }
+        let input = helper(&format!("{} {}", commands[0], seed), b"", slow, &plan.generator, seed)?;
+        let answer = helper(&commands[1], input.as_bytes(), slow, &plan.brute, seed)?;
+        let settings = Settings { command: commands[2].clone(), ..plan.settings.clone() };
+        let outcome = judge_input(input.as_bytes(), Some(&answer), &settings);
+        if outcome.verdict != Verdict::Accepted {
+            return Ok(Report::Failed(Failure { seed, input, answer, outcome }));
+        }
+        sender.send(Report::Passed(seed as usize)).ok();
+    }
+    Ok(Report::Finished(plan.iterations))
+}
+
+/// Output of the generator or the brute force, which must not fail.
+fn helper(command: &str, input: &[u8], limits: Limits, source: &Path, seed: u64) -> Result<String, String> {
+    let execution = process::execute(command, input, &limits).map_err(|e| format!("{}: {}", command, e))?;
+    let name = source.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned());
+    match execution.status {
+        Status::Exited(0) => Ok(execution.stdout),
+        Status::Exited(code) => Err(format!("{} exited with code {} on seed {}", name, code, seed)),
+        Status::Signaled(signal) => Err(format!("{} died of {} on seed {}", name, process::signal_name(signal), seed)),
+        Status::TimedOut => Err(format!("{} took over {} ms on seed {}", name, limits.wall_time.as_millis(), seed)),
+        Status::OutputLimitExceeded => Err(format!("{} wrote too much on seed {}", name, seed)),
+    }
+}
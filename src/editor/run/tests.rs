@@ -0,0 +1,219 @@
+use super::compare::{self, Diff, Mode};
+use super::process::{self, Limits, Status};
+use super::store::{self, Store};
+use super::{find_cases, judge_input, Case, Program, Settings, Verdict};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[test]
+fn tokens_ignore_spacing() {
+    assert_eq!(compare::check("1 2\n3\n\n", "1  2 3", Mode::Tokens), Ok(()));
+    assert_eq!(compare::check("1 2\n4\n", "1 2\n3\n", Mode::Tokens), Err("line 2: expected '3', got '4'".to_string()));
+    assert_eq!(compare::check("1\n", "1 2\n", Mode::Tokens), Err("line 1: output ended, expected '2'".to_string()));
+    assert!(compare::check("1 2 3\n", "1 2\n", Mode::Tokens).is_err());
+}
+
+#[test]
+fn exact_keeps_lines_apart() {
+    assert_eq!(compare::check("a b  \nc\n\n", "a b\nc", Mode::Exact), Ok(()));
+    assert!(compare::check("a b c\n", "a b\nc\n", Mode::Exact).is_err());
+}
+
+#[test]
+fn floats_within_epsilon() {
+    let mode = Mode::Float(1e-6);
+    assert_eq!(compare::check("0.3333333\n", "0.333333333", mode), Ok(()));
+    assert_eq!(compare::check("1000.5\n", "1000.0", mode), Err("line 1: expected '1000.0', got '1000.5'".to_string()));
+    assert_eq!(compare::check("2000000000.001\n", "2000000000", mode), Ok(()));
+    assert!(compare::check("0.34\n", "0.33", mode).is_err());
+    assert!(compare::check("0.3333333\n", "0.333333333", Mode::Tokens).is_err());
+}
+
+#[test]
+fn diff_marks_changed_lines() {
+    let diff = compare::diff("a\nb\nc\n", "a\nx\nc\nd\n");
+    assert_eq!(
+        diff,
+        vec![
+            Diff::Same("a".to_string()),
+            Diff::Expected("b".to_string()),
+            Diff::Got("x".to_string()),
+            Diff::Same("c".to_string()),
+            Diff::Got("d".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn measures_and_times_out() {
+    let execution = process::execute("cat", b"hello\n", &Limits::time(Duration::from_secs(5))).unwrap();
+    assert_eq!(execution.status, Status::Exited(0));
+    assert_eq!(execution.stdout, "hello\n");
+    assert!(execution.memory_kb > 0);
+
+    let execution = process::execute("sleep 5", b"", &Limits::time(Duration::from_millis(100))).unwrap();
+    assert_eq!(execution.status, Status::TimedOut);
+    assert!(execution.time < Duration::from_secs(2));
+
+    let execution = process::execute("kill -SEGV $$", b"", &Limits::time(Duration::from_secs(5))).unwrap();
+    assert_eq!(execution.status, Status::Signaled(libc::SIGSEGV));
+}
+
+#[test]
+fn finds_tests_named_after_the_source() {
+    let dir = std::env::temp_dir().join(format!("vimr-run-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for name in ["a.cpp", "a1.in", "a1.out", "a10.in", "a2.in", "a2.ans", "ab.in", "input.txt"] {
+        fs::write(dir.join(name), "").unwrap();
+    }
+    let cases = find_cases(&dir.join("a.cpp"));
+    let case = |name: &str, answer: Option<&str>| Case {
+        name: name.to_string(),
+        input: dir.join(name),
+        answer: answer.map(|a| dir.join(a)),
+    };
+    assert_eq!(cases, vec![case("a1.in", Some("a1.out")), case("a2.in", Some("a2.ans")), case("a10.in", None)]);
+
+    let cases = find_cases(&dir.join("b.cpp"));
+    assert_eq!(cases.iter().map(|c| c.input.clone()).collect::<Vec<PathBuf>>(), vec![dir.join("input.txt")]);
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn stores_and_renumbers_tests() {
+    let dir = std::env::temp_dir().join(format!("vimr-store-{}", std::process::id()));
+    let store = Store::for_source(&dir.join("a.cpp"));
+    assert_eq!(store.dir, dir.join(".vimr/tests/a"));
+    assert_eq!(store.add("1\n", "2\n").unwrap(), 1);
+    assert_eq!(store.add("3\n", "").unwrap(), 2);
+    assert_eq!(store.add("5\n", "10\n").unwrap(), 3);
+    store.set_enabled(3, false).unwrap();
+    store.delete(1).unwrap();
+    assert_eq!(store.renumber().unwrap(), 2);
+
+    let tests = store.tests();
+    assert_eq!(tests.iter().map(|t| (t.number, t.enabled)).collect::<Vec<_>>(), vec![(1, true), (2, false)]);
+    assert_eq!(store.read(2).unwrap(), ("5\n".to_string(), "10\n".to_string()));
+
+    // Disabled tests and empty answers.
+    let cases = find_cases(&dir.join("a.cpp"));
+    assert_eq!(cases, vec![Case { name: "#01".to_string(), input: tests[0].input.clone(), answer: None }]);
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn edits_a_test_as_one_text() {
+    let text = store::to_text("1 2\n", "3");
+    assert_eq!(text, "### input\n1 2\n### output\n3\n");
+    assert_eq!(store::parse_text(&text), Ok(("1 2\n".to_string(), "3\n".to_string())));
+    assert_eq!(store::parse_text("### input\n### output"), Ok((String::new(), String::new())));
+    assert!(store::parse_text("1 2\n### output\n3").is_err());
+}
+
+#[test]
+fn judges_with_a_checker_and_an_interactor() {
+    let dir = std::env::temp_dir().join(format!("vimr-helpers-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let script = |name: &str, text: &str| {
+        let path = dir.join(name);
+        fs::write(&path, text).unwrap();
+        Program { source: path.clone(), build_command: String::new(), command: format!("sh {}", path.display()) }
+    };
+    // Any answer of the right parity will do.
+    let checker = script(
+        "checker.sh",
+        r#"read a < "$2"; read b < "$3"
+if [ $((a % 2)) -eq $((b % 2)) ]; then echo "ok same parity" >&2; exit 0; fi
+echo "wrong answer parity differs" >&2; exit 1"#,
+    );
+    let settings = Settings {
+        command: "cat".to_string(),
+        limits: Limits::time(Duration::from_secs(5)),
+        mode: Mode::Exact,
+        checker: Some(checker),
+        interactor: None,
+    };
+    let outcome = judge_input(b"3\n", Some("5\n"), &settings);
+    assert_eq!((outcome.verdict, outcome.detail.as_str()), (Verdict::Accepted, "ok same parity"));
+    let outcome = judge_input(b"4\n", Some("5\n"), &settings);
+    assert_eq!((outcome.verdict, outcome.detail.as_str()), (Verdict::WrongAnswer, "wrong answer parity differs"));
+
+    let interactor = script(
+        "interactor.sh",
+        r#"read n < "$1"; echo ready; read guess
+if [ "$guess" = "$n" ]; then echo "ok guessed" >&2; echo done; exit 0; fi
+echo "wrong answer got $guess" >&2; exit 1"#,
+    );
+    let settings = Settings {
+        command: "sh -c 'read ready; echo 7; read done'".to_string(),
+        checker: None,
+        interactor: Some(interactor),
+        ..settings
+    };
+    let outcome = judge_input(b"7\n", None, &settings);
+    assert_eq!((outcome.verdict, outcome.detail.as_str()), (Verdict::Accepted, "ok guessed"));
+    assert_eq!(outcome.transcript, vec!["< ready", "> 7", "< done"]);
+    let outcome = judge_input(b"8\n", None, &settings);
+    assert_eq!((outcome.verdict, outcome.detail.as_str()), (Verdict::WrongAnswer, "wrong answer got 7"));
+
+    let settings = Settings { command: "sleep 5".to_string(), limits: Limits::time(Duration::from_millis(100)), ..settings };
+    assert_eq!(judge_input(b"7\n", None, &settings).verdict, Verdict::TimeLimitExceeded);
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn enforces_limits() {
+    let limits = Limits { time: Duration::from_millis(300), wall_time: Duration::from_secs(5), memory: 64 << 20, output: 1 << 20 };
+    let settings = |command: &str| Settings {
+        command: command.to_string(),
+        limits,
+        mode: Mode::Tokens,
+        checker: None,
+        interactor: None,
+    };
+    let judge = |command: &str| judge_input(b"", None, &settings(command));
+
+    // In kilobytes and seconds.
+    let outcome = judge("sh -c 'ulimit -v; ulimit -s; ulimit -t'");
+    assert_eq!((outcome.verdict, outcome.output.as_str()), (Verdict::Finished, "65536\n65536\n1\n"));
+    let outcome = judge("yes");
+    assert_eq!((outcome.verdict, outcome.output.len()), (Verdict::OutputLimitExceeded, 1 << 20));
+    assert_eq!(judge("sh -c 'while :; do :; done'").verdict, Verdict::TimeLimitExceeded);
+    let outcome = judge("sh -c 'echo \"terminate called after throwing an instance of std::bad_alloc\" >&2; kill -ABRT $$'");
+    assert_eq!(outcome.verdict, Verdict::MemoryLimitExceeded);
+
+    // Killing it on the clock takes what it started too, or reading its
+    // output would wait for the sleep.
+    let started = std::time::Instant::now();
+    let settings = Settings { limits: Limits { wall_time: Duration::from_millis(100), ..limits }, ..settings("sh -c 'sleep 5 & wait'") };
+    assert_eq!(judge_input(b"", None, &settings).verdict, Verdict::TimeLimitExceeded);
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn reports_running_out_of_memory() {
+    let dir = std::env::temp_dir().join(format!("vimr-memory-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("mle.cpp");
+    fs::write(&source, "#include <vector>\nint main() { std::vector<char> v(1 << 28, 1); return v[12345] - 1; }\n").unwrap();
+    let program = dir.join("mle");
+    let built = std::process::Command::new("g++").arg(&source).arg("-o").arg(&program).status();
+    if !built.is_ok_and(|status| status.success()) {
+        // No compiler here.
+        fs::remove_dir_all(&dir).ok();
+        return;
+    }
+    let settings = |memory: u64| Settings {
+        command: program.display().to_string(),
+        limits: Limits { memory, ..Limits::time(Duration::from_secs(5)) },
+        mode: Mode::Tokens,
+        checker: None,
+        interactor: None,
+    };
+    let outcome = judge_input(b"", None, &settings(64 << 20));
+    assert_eq!((outcome.verdict, outcome.detail.as_str()), (Verdict::MemoryLimitExceeded, "memory limit 64.0 MB"));
+    assert!(outcome.stderr.contains("std::bad_alloc"));
+    assert_eq!(judge_input(b"", None, &settings(1 << 30)).verdict, Verdict::Finished);
+    fs::remove_dir_all(&dir).ok();
+}
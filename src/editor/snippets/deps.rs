@@ -0,0 +1,154 @@
+//! What a snippet needs from the rest of the file: headers, `using
+//! namespace` lines and other snippets it builds on. Expanding a snippet
+//! adds whichever of these the file does not have yet.
+
+use crate::editor::snippets::{engine, Snippet};
+use std::collections::{HashMap, HashSet};
+
+/// Everything missing from a file for a snippet to compile in it.
+#[derive(Default)]
+pub struct Requirements {
+    /// `#include` and `using namespace` lines, in the order they go in.
+    pub preamble: Vec<String>,
+    /// Triggers of the snippets to insert, dependencies first.
+    pub snippets: Vec<String>,
+}
+
+impl Requirements {
+    pub fn is_empty(&self) -> bool {
+        self.preamble.is_empty() && self.snippets.is_empty()
+    }
+}
+
+/// What expanding `trigger` into `content` has to add. Dependencies that
+/// are already in the file, or not known, are left out, along with what
+/// they need in turn.
+pub fn requirements(trigger: &str, snippets: &HashMap<String, Snippet>, content: &[String]) -> Requirements {
+    let mut order = Vec::new();
+    visit(trigger, snippets, content, &mut HashSet::new(), &mut order);
+
+    let includes: HashSet<String> = content.iter().filter_map(|line| include(line)).collect();
+    let everything = includes.contains("<bits/stdc++.h>");
+    let namespaces: HashSet<String> = content.iter().filter_map(|line| using_namespace(line)).collect();
+
+    let mut missing_includes: Vec<String> = Vec::new();
+    let mut missing_namespaces: Vec<String> = Vec::new();
+    for snippet in order.iter().map(|t| &snippets[*t]) {
+        for header in snippet.headers.iter().map(|h| header(h)) {
+            let present = includes.contains(&header) || (everything && header.starts_with('<'));
+            if !present && !missing_includes.contains(&header) {
+                missing_includes.push(header);
+            }
+        }
+        for namespace in &snippet.namespaces {
+            if !namespaces.contains(namespace) && !missing_namespaces.contains(namespace) {
+                missing_namespaces.push(namespace.clone());
+            }
+        }
+    }
+
+    let mut preamble: Vec<String> = missing_includes.iter().map(|h| format!("#include {}", h)).collect();
+    preamble.extend(missing_namespaces.iter().map(|n| format!("using namespace {};", n)));
+    order.pop();
+    Requirements { preamble, snippets: order.into_iter().map(String::from).collect() }
+}
+
+// Appends `trigger` to `order` after the dependencies the file is missing.
+fn visit<'a>(
+    trigger: &'a str,
+    snippets: &'a HashMap<String, Snippet>,
+    content: &[String],
+    seen: &mut HashSet<&'a str>,
+    order: &mut Vec<&'a str>,
+) {
+    let Some((trigger, snippet)) = snippets.get_key_value(trigger) else {
+        return;
+    };
+    if !seen.insert(trigger) {
+        return;
+    }
+    for dependency in &snippet.requires {
+        if snippets.get(dependency).is_some_and(|s| !is_present(s, content)) {
+            visit(dependency, snippets, content, seen, order);
+        }
+    }
+    order.push(trigger);
+}
+
+/// A header as written after `#include`: `queue` becomes `<queue>`, while
+/// `<queue>` and `"local.h"` stay as they are.
+fn header(name: &str) -> String {
+    let name = name.trim();
+    if name.starts_with('<') || name.starts_with('"') {
+        name.to_string()
+    } else {
+        format!("<{}>", name)
+    }
+}
+
+/// The header an `#include` line names, with its brackets or quotes.
+fn include(line: &str) -> Option<String> {
+    let rest = line.trim().strip_prefix('#')?.trim_start().strip_prefix("include")?.trim_start();
+    let close = match rest.chars().next()? {
+        '<' => '>',
+        '"' => '"',
+        _ => return None,
+    };
+    let end = rest[1..].find(close)? + 1;
+    Some(rest[..=end].to_string())
+}
+
+fn using_namespace(line: &str) -> Option<String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["using", "namespace", name] => name.strip_suffix(';').map(String::from),
+        _ => None,
+    }
+}
+
+fn squash(line: &str) -> String {
+    line.split_whitespace().collect()
+}
+
+/// Whether `content` already has the snippet, going by the line that opens
+/// its first top-level definition (`struct mint {`), ignoring spacing.
+fn is_present(snippet: &Snippet, content: &[String]) -> bool {
+    let expansion = engine::expand(&snippet.body, &|_| None);
+    let lines = &expansion.lines;
+    let signature = lines
+        .iter()
+        .find(|line| {
+            !line.starts_with(char::is_whitespace) && line.trim_end().ends_with('{') && !line.starts_with("template")
+        })
+        .or_else(|| lines.iter().find(|line| !line.trim().is_empty() && !line.trim_start().starts_with("//")));
+    let Some(signature) = signature.map(|line| squash(line)) else {
+        return false;
+    };
+    content.iter().any(|line| squash(line) == signature)
+}
+
+/// The line after the last `#include`, where missing ones are added; the
+/// top of the file if there are none.
+pub fn include_line(content: &[String]) -> usize {
+    content.iter().rposition(|line| include(line).is_some()).map_or(0, |i| i + 1)
+}
+
+/// The line dependencies of a snippet expanded on line `y` go above: the
+/// start of the top-level definition `y` is in, or `y` itself at the top
+/// level.
+pub fn insertion_line(content: &[String], y: usize) -> usize {
+    let nested = |line: &String| line.is_empty() || line.starts_with([' ', '\t', '{']);
+    if !content[y].starts_with([' ', '\t']) {
+        return y;
+    }
+    let Some(mut start) = (0..y).rev().find(|&i| !nested(&content[i])) else {
+        return y;
+    };
+    if content[start].starts_with('}') {
+        return y;
+    }
+    if start > 0 && content[start - 1].starts_with("template") {
+        start -= 1;
+    }
+    start
+}
@@ -0,0 +1,700 @@
+//! Snippet body syntax and the placeholder session that follows an
+//! expansion.
+//!
+//! Bodies are plain text with these tabstop forms:
+//!
+//! - `$1`, `${1}`: an empty tabstop
+//! - `${1:default}`: a tabstop with default text, which may itself contain
+//!   tabstops
+//! - `${1|int,long long|}`: a tabstop offering a list of choices
+//! - `$0`: where the cursor ends up; added at the end if missing
+//!
+//! A tabstop number used more than once is mirrored: the first occurrence
+//! is edited and the others follow it. `\$` and `\}` produce literal
+//! characters; any other backslash is kept as is, so C++ escapes such as
+//! `"\n"` need no quoting.
+//!
+//! Variables are written `$NAME`, `${NAME}` or `${NAME:default}` and are
+//! resolved when the snippet expands; an unknown variable is left as
+//! written unless it has a default. A mirror or variable may be
+//! transformed with `${1/regex/format/flags}`, where the format refers to
+//! capture groups as `$1`, `${1:/upcase}` (also `downcase`, `capitalize`,
+//! `camelcase`, `pascalcase`), `${1:+if matched}`, `${1:-otherwise}` and
+//! `${1:?if matched:otherwise}`. Flags are `g`, `i` and `m`.
+
+use regex::{Captures, Regex, RegexBuilder};
+use std::collections::HashMap;
+
+enum Node {
+    Text(String),
+    Tabstop {
+        index: usize,
+        children: Vec<Node>,
+        choices: Vec<String>,
+        transform: Option<Transform>,
+    },
+    Variable {
+        name: String,
+        default: Vec<Node>,
+        transform: Option<Transform>,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Case {
+    Upcase,
+    Downcase,
+    Capitalize,
+    Camelcase,
+    Pascalcase,
+}
+
+#[derive(Clone)]
+enum FormatItem {
+    Text(String),
+    Group(usize),
+    Case(usize, Case),
+    /// `present` is `None` when a matched group stands for itself.
+    Conditional {
+        group: usize,
+        present: Option<String>,
+        absent: String,
+    },
+}
+
+/// A regex replacement applied to the text of a mirror or variable.
+#[derive(Clone)]
+pub struct Transform {
+    regex: Regex,
+    format: Vec<FormatItem>,
+    global: bool,
+}
+
+impl Case {
+    fn apply(self, text: &str) -> String {
+        let mut chars = text.chars();
+        match self {
+            Case::Upcase => text.to_uppercase(),
+            Case::Downcase => text.to_lowercase(),
+            Case::Capitalize => chars.next().map_or(String::new(), |c| c.to_uppercase().chain(chars).collect()),
+            Case::Camelcase | Case::Pascalcase => {
+                let words = text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty());
+                words
+                    .enumerate()
+                    .map(|(i, word)| {
+                        if i == 0 && matches!(self, Case::Camelcase) {
+                            word.to_lowercase()
+                        } else {
+                            Case::Capitalize.apply(&word.to_lowercase())
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+impl Transform {
+    fn new(regex: &str, format: &str, flags: &str) -> Option<Self> {
+        let regex = RegexBuilder::new(regex)
+            .case_insensitive(flags.contains('i'))
+            .multi_line(flags.contains('m'))
+            .build()
+            .ok()?;
+        Some(Transform { regex, format: parse_format(format), global: flags.contains('g') })
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        let replace = |caps: &Captures| {
+            let group = |n: usize| caps.get(n).map_or("", |m| m.as_str());
+            let mut out = String::new();
+            for item in self.format.iter() {
+                match item {
+                    FormatItem::Text(t) => out.push_str(t),
+                    FormatItem::Group(n) => out.push_str(group(*n)),
+                    FormatItem::Case(n, case) => out.push_str(&case.apply(group(*n))),
+                    FormatItem::Conditional { group: n, present, absent } => {
+                        match (caps.get(*n).filter(|m| !m.as_str().is_empty()), present) {
+                            (Some(_), Some(present)) => out.push_str(present),
+                            (Some(m), None) => out.push_str(m.as_str()),
+                            (None, _) => out.push_str(absent),
+                        }
+                    }
+                }
+            }
+            out
+        };
+        if self.global {
+            self.regex.replace_all(text, replace).into_owned()
+        } else {
+            self.regex.replace(text, replace).into_owned()
+        }
+    }
+}
+
+// Reads up to an unescaped `end`, dropping the backslash of `\end` and
+// keeping other escapes for the regex engine to see.
+fn read_until(chars: &mut std::iter::Peekable<std::str::Chars>, end: char) -> String {
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&end) => text.extend(chars.next()),
+            '\\' => {
+                text.push(c);
+                text.extend(chars.next());
+            }
+            c if c == end => break,
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+fn parse_format(format: &str) -> Vec<FormatItem> {
+    let mut items = Vec::new();
+    let mut text = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            text.extend(chars.next());
+            continue;
+        }
+        let braced = c == '$' && chars.peek() == Some(&'{');
+        if c != '$' || !(braced || chars.peek().is_some_and(char::is_ascii_digit)) {
+            text.push(c);
+            continue;
+        }
+        if braced {
+            chars.next();
+        }
+        let mut digits = String::new();
+        while let Some(d) = chars.next_if(char::is_ascii_digit) {
+            digits.push(d);
+        }
+        let group = digits.parse().unwrap_or(0);
+        let item = if !braced {
+            FormatItem::Group(group)
+        } else if chars.next_if_eq(&':').is_none() {
+            chars.next_if_eq(&'}');
+            FormatItem::Group(group)
+        } else {
+            match chars.peek() {
+                Some('/') => {
+                    chars.next();
+                    match read_until(&mut chars, '}').as_str() {
+                        "upcase" => FormatItem::Case(group, Case::Upcase),
+                        "downcase" => FormatItem::Case(group, Case::Downcase),
+                        "capitalize" => FormatItem::Case(group, Case::Capitalize),
+                        "camelcase" => FormatItem::Case(group, Case::Camelcase),
+                        "pascalcase" => FormatItem::Case(group, Case::Pascalcase),
+                        _ => FormatItem::Group(group),
+                    }
+                }
+                Some('+') => {
+                    chars.next();
+                    let present = read_until(&mut chars, '}');
+                    FormatItem::Conditional { group, present: Some(present), absent: String::new() }
+                }
+                Some('?') => {
+                    chars.next();
+                    let present = read_until(&mut chars, ':');
+                    let absent = read_until(&mut chars, '}');
+                    FormatItem::Conditional { group, present: Some(present), absent }
+                }
+                _ => {
+                    chars.next_if_eq(&'-');
+                    let absent = read_until(&mut chars, '}');
+                    FormatItem::Conditional { group, present: None, absent }
+                }
+            }
+        };
+        if !text.is_empty() {
+            items.push(FormatItem::Text(std::mem::take(&mut text)));
+        }
+        items.push(item);
+    }
+    if !text.is_empty() {
+        items.push(FormatItem::Text(text));
+    }
+    items
+}
+
+/// A span of text on a single line, in byte columns.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Range {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Range {
+    pub fn contains(&self, line: usize, col: usize) -> bool {
+        self.line == line && self.start <= col && col <= self.end
+    }
+}
+
+pub struct Tabstop {
+    pub index: usize,
+    /// The first range is the one edited; the others are mirrors.
+    pub ranges: Vec<Range>,
+    /// The transform of each range, if any; never set for the first.
+    pub transforms: Vec<Option<Transform>>,
+    pub choices: Vec<String>,
+}
+
+/// A snippet body with its tabstops resolved, ready to be inserted.
+pub struct Expansion {
+    pub lines: Vec<String>,
+    pub tabstops: Vec<Tabstop>,
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    // Parses until an unescaped `}` when `nested`, or the end of input.
+    fn nodes(&mut self, nested: bool) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        let mut text = String::new();
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                '}' if nested => break,
+                '\\' => {
+                    self.chars.next();
+                    match self.chars.peek() {
+                        Some(&next @ ('$' | '}')) => {
+                            text.push(next);
+                            self.chars.next();
+                        }
+                        _ => text.push('\\'),
+                    }
+                }
+                '$' => {
+                    self.chars.next();
+                    match self.tabstop() {
+                        Some(node) => {
+                            if !text.is_empty() {
+                                nodes.push(Node::Text(std::mem::take(&mut text)));
+                            }
+                            nodes.push(node);
+                        }
+                        None => text.push('$'),
+                    }
+                }
+                _ => {
+                    text.push(c);
+                    self.chars.next();
+                }
+            }
+        }
+        if !text.is_empty() {
+            nodes.push(Node::Text(text));
+        }
+        nodes
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let mut digits = String::new();
+        while let Some(&c) = self.chars.peek().filter(|c| c.is_ascii_digit()) {
+            digits.push(c);
+            self.chars.next();
+        }
+        digits.parse().ok()
+    }
+
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || *c == '_') {
+            name.push(c);
+        }
+        name
+    }
+
+    // Reads a transform's format up to its closing `/`, which may not be
+    // inside a `${...}` group.
+    fn format(&mut self) -> String {
+        let mut format = String::new();
+        let mut depth = 0;
+        while let Some(c) = self.chars.next() {
+            match c {
+                '\\' => {
+                    format.push(c);
+                    format.extend(self.chars.next());
+                }
+                '$' if self.chars.peek() == Some(&'{') => {
+                    depth += 1;
+                    format.push(c);
+                    format.extend(self.chars.next());
+                }
+                '}' if depth > 0 => {
+                    depth -= 1;
+                    format.push(c);
+                }
+                '/' if depth == 0 => break,
+                c => format.push(c),
+            }
+        }
+        format
+    }
+
+    // Reads `regex/format/flags}` after the opening `/`.
+    fn transform(&mut self) -> Option<Transform> {
+        let regex = read_until(&mut self.chars, '/');
+        let format = self.format();
+        let flags = read_until(&mut self.chars, '}');
+        Transform::new(&regex, &format, &flags)
+    }
+
+    // Called after a `$`. Returns `None`, having consumed nothing, when the
+    // `$` does not start a tabstop or variable.
+    fn tabstop(&mut self) -> Option<Node> {
+        let is_name_start = |c: &char| c.is_ascii_uppercase() || *c == '_';
+        match self.chars.peek() {
+            Some(c) if c.is_ascii_digit() => {
+                let index = self.number()?;
+                Some(Node::Tabstop { index, children: Vec::new(), choices: Vec::new(), transform: None })
+            }
+            Some(c) if is_name_start(c) => {
+                Some(Node::Variable { name: self.name(), default: Vec::new(), transform: None })
+            }
+            Some('{') => {
+                // Only commit to `${` once we know a number or name follows.
+                let mut lookahead = self.chars.clone();
+                lookahead.next();
+                let variable = lookahead.peek().is_some_and(is_name_start);
+                if !variable && !lookahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    return None;
+                }
+                self.chars.next();
+                let (index, name) = if variable { (0, self.name()) } else { (self.number()?, String::new()) };
+                let (mut children, mut choices, mut transform) = (Vec::new(), Vec::new(), None);
+                match self.chars.next() {
+                    Some(':') => {
+                        children = self.nodes(true);
+                        self.chars.next();
+                    }
+                    Some('|') if !variable => choices = self.choices(),
+                    Some('/') => transform = self.transform(),
+                    _ => {}
+                }
+                Some(if variable {
+                    Node::Variable { name, default: children, transform }
+                } else {
+                    Node::Tabstop { index, children, choices, transform }
+                })
+            }
+            _ => None,
+        }
+    }
+
+    // Reads `a,b|}` after the opening `|`.
+    fn choices(&mut self) -> Vec<String> {
+        let mut choices = Vec::new();
+        let mut current = String::new();
+        while let Some(c) = self.chars.next() {
+            match c {
+                '\\' => {
+                    if let Some(next) = self.chars.next() {
+                        current.push(next);
+                    }
+                }
+                ',' => choices.push(std::mem::take(&mut current)),
+                '|' if self.chars.peek() == Some(&'}') => {
+                    self.chars.next();
+                    break;
+                }
+                _ => current.push(c),
+            }
+        }
+        choices.push(current);
+        choices
+    }
+}
+
+struct Renderer<'a> {
+    text: String,
+    line: usize,
+    col: usize,
+    defaults: HashMap<usize, String>,
+    ranges: HashMap<usize, Vec<(Range, Option<Transform>)>>,
+    choices: HashMap<usize, Vec<String>>,
+    resolve: &'a dyn Fn(&str) -> Option<String>,
+}
+
+impl Renderer<'_> {
+    fn push(&mut self, s: &str) {
+        for c in s.chars() {
+            self.text.push(c);
+            if c == '\n' {
+                self.line += 1;
+                self.col = 0;
+            } else {
+                self.col += c.len_utf8();
+            }
+        }
+    }
+
+    // Pushes text that may span lines, such as a variable's value, keeping
+    // continuation lines at the indent of the current line.
+    fn push_indented(&mut self, s: &str) {
+        let line_start = self.text.rfind('\n').map_or(0, |i| i + 1);
+        let current = &self.text[line_start..];
+        let indent = current[..current.len() - current.trim_start().len()].to_string();
+        self.push(&s.replace('\n', &format!("\n{}", indent)));
+    }
+
+    // Collects the default text of every tabstop from its first occurrence
+    // that has one, so mirrors can show it too.
+    fn collect_defaults(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            match node {
+                Node::Tabstop { index, children, choices, .. } => {
+                    if !self.defaults.contains_key(index) {
+                        if let Some(first) = choices.first() {
+                            self.defaults.insert(*index, first.clone());
+                            self.choices.insert(*index, choices.clone());
+                        } else if !children.is_empty() {
+                            let text = plain_text(children, &HashMap::new(), self.resolve);
+                            self.defaults.insert(*index, text);
+                        }
+                    }
+                    self.collect_defaults(children);
+                }
+                Node::Variable { default, .. } => self.collect_defaults(default),
+                Node::Text(_) => {}
+            }
+        }
+    }
+
+    fn render(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            match node {
+                Node::Text(text) => self.push(text),
+                Node::Variable { name, default, transform } => {
+                    match (self.resolve)(name).filter(|value| !value.is_empty()) {
+                        Some(value) => {
+                            let value = transform.as_ref().map_or(value.clone(), |t| t.apply(&value));
+                            self.push_indented(&value);
+                        }
+                        None if !default.is_empty() => self.render(default),
+                        None => self.push(&format!("${}", name)),
+                    }
+                }
+                Node::Tabstop { index, children, transform, .. } => {
+                    let start = (self.line, self.col);
+                    let primary = transform.is_none()
+                        && !self.ranges.get(index).is_some_and(|r| r.iter().any(|(_, t)| t.is_none()));
+                    if primary && !children.is_empty() {
+                        self.render(children);
+                    } else {
+                        let text = self.defaults.get(index).cloned().unwrap_or_default();
+                        let text = transform.as_ref().map_or(text.clone(), |t| t.apply(&text));
+                        self.push(&text);
+                    }
+                    // Mirrors are single-line; a default spanning lines is
+                    // only tracked on the line it starts on.
+                    let end = if self.line == start.0 { self.col } else { start.1 };
+                    let range = Range { line: start.0, start: start.1, end };
+                    self.ranges.entry(*index).or_default().push((range, transform.clone()));
+                }
+            }
+        }
+    }
+}
+
+fn plain_text(nodes: &[Node], defaults: &HashMap<usize, String>, resolve: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut text = String::new();
+    for node in nodes {
+        match node {
+            Node::Text(t) => text.push_str(t),
+            Node::Tabstop { index, children, choices, .. } => {
+                if let Some(first) = choices.first() {
+                    text.push_str(first);
+                } else if !children.is_empty() {
+                    text.push_str(&plain_text(children, defaults, resolve));
+                } else if let Some(default) = defaults.get(index) {
+                    text.push_str(default);
+                }
+            }
+            Node::Variable { name, default, transform } => match resolve(name).filter(|v| !v.is_empty()) {
+                Some(value) => text.push_str(&transform.as_ref().map_or(value.clone(), |t| t.apply(&value))),
+                None => text.push_str(&plain_text(default, defaults, resolve)),
+            },
+        }
+    }
+    text
+}
+
+/// Parses a snippet body and lays out its text and tabstops, looking up
+/// variables with `resolve`.
+pub fn expand(body: &str, resolve: &dyn Fn(&str) -> Option<String>) -> Expansion {
+    let mut parser = Parser { chars: body.chars().peekable() };
+    let mut nodes = parser.nodes(false);
+    // A stray `}` at top level is literal text.
+    while parser.chars.next().is_some() {
+        nodes.push(Node::Text("}".to_string()));
+        nodes.extend(parser.nodes(false));
+    }
+
+    let mut renderer = Renderer {
+        text: String::new(),
+        line: 0,
+        col: 0,
+        defaults: HashMap::new(),
+        ranges: HashMap::new(),
+        choices: HashMap::new(),
+        resolve,
+    };
+    renderer.collect_defaults(&nodes);
+    renderer.render(&nodes);
+    if !renderer.ranges.contains_key(&0) {
+        let end = Range { line: renderer.line, start: renderer.col, end: renderer.col };
+        renderer.ranges.insert(0, vec![(end, None)]);
+    }
+
+    let mut indexes: Vec<usize> = renderer.ranges.keys().copied().collect();
+    // Jump order is 1, 2, ..., with the final position `$0` last.
+    indexes.sort_by_key(|&i| (i == 0, i));
+    let mut tabstops = Vec::new();
+    for index in indexes {
+        let mut ranges = renderer.ranges.remove(&index).unwrap_or_default();
+        // The first untransformed occurrence is the one the user edits; a
+        // tabstop that only appears transformed cannot be edited at all.
+        let Some(primary) = ranges.iter().position(|(_, t)| t.is_none()) else {
+            continue;
+        };
+        let first = ranges.remove(primary);
+        ranges.insert(0, first);
+        let (ranges, transforms) = ranges.into_iter().unzip();
+        tabstops.push(Tabstop {
+            index,
+            ranges,
+            transforms,
+            choices: renderer.choices.remove(&index).unwrap_or_default(),
+        });
+    }
+
+    Expansion {
+        lines: renderer.text.split('\n').map(String::from).collect(),
+        tabstops,
+    }
+}
+
+/// The tabstops of an expanded snippet, in buffer coordinates, while the
+/// user fills them in.
+pub struct Session {
+    pub tabstops: Vec<Tabstop>,
+    pub current: usize,
+    /// Set on arriving at a tabstop: the first edit replaces its default.
+    pub fresh: bool,
+}
+
+impl Session {
+    /// Places `expansion` with its first line starting at `line`, `col`.
+    pub fn new(expansion: Expansion, line: usize, col: usize) -> Self {
+        let mut tabstops = expansion.tabstops;
+        for range in tabstops.iter_mut().flat_map(|t| t.ranges.iter_mut()) {
+            if range.line == 0 {
+                range.start += col;
+                range.end += col;
+            }
+            range.line += line;
+        }
+        Session { tabstops, current: 0, fresh: true }
+    }
+
+    pub fn tabstop(&self) -> &Tabstop {
+        &self.tabstops[self.current]
+    }
+
+    pub fn primary(&self) -> Range {
+        self.tabstop().ranges[0]
+    }
+
+    /// Whether the current tabstop is the final cursor position.
+    pub fn at_end(&self) -> bool {
+        self.tabstop().index == 0
+    }
+
+    /// Moves to the next (`forward`) or previous tabstop. Returns false if
+    /// there is none in that direction.
+    pub fn jump(&mut self, forward: bool) -> bool {
+        let next = if forward {
+            self.current + 1
+        } else {
+            match self.current.checked_sub(1) {
+                Some(prev) => prev,
+                None => return false,
+            }
+        };
+        if next >= self.tabstops.len() {
+            return false;
+        }
+        self.current = next;
+        self.fresh = true;
+        true
+    }
+
+    /// Updates ranges after `removed` bytes at `at` on `line` were replaced
+    /// by `inserted` bytes. `active` is the (tabstop, range) the edit
+    /// belongs to, which grows to include text typed at its edges.
+    pub fn edited(&mut self, line: usize, at: usize, removed: usize, inserted: usize, active: Option<(usize, usize)>) {
+        let edit_end = at + removed;
+        for (t, tabstop) in self.tabstops.iter_mut().enumerate() {
+            for (r, range) in tabstop.ranges.iter_mut().enumerate() {
+                if range.line != line {
+                    continue;
+                }
+                if active == Some((t, r)) || (range.start < at && range.end >= edit_end) {
+                    range.end = (range.end + inserted).saturating_sub(removed).max(range.start);
+                } else if range.start >= edit_end {
+                    range.start = range.start + inserted - removed;
+                    range.end = range.end + inserted - removed;
+                } else if range.end > at {
+                    range.start = range.start.min(at);
+                    range.end = at + inserted;
+                }
+            }
+        }
+    }
+
+    /// Updates ranges after `delta` lines were inserted or removed below
+    /// `line`.
+    pub fn lines_changed(&mut self, line: usize, delta: isize) {
+        for range in self.tabstops.iter_mut().flat_map(|t| t.ranges.iter_mut()) {
+            if range.line > line {
+                range.line = range.line.saturating_add_signed(delta);
+            }
+        }
+    }
+
+    /// The tabstop whose primary range contains the cursor, preferring the
+    /// current one. Mirrors are never edited directly.
+    pub fn tabstop_at(&self, line: usize, col: usize) -> Option<usize> {
+        if self.primary().contains(line, col) {
+            return Some(self.current);
+        }
+        self.tabstops.iter().position(|t| t.ranges[0].contains(line, col))
+    }
+
+    /// The first mirror whose text differs from its primary range, with
+    /// the text it should have.
+    pub fn stale_mirror(&self, content: &[String]) -> Option<(usize, usize, String)> {
+        for (t, tabstop) in self.tabstops.iter().enumerate() {
+            let primary = tabstop.ranges[0];
+            let text = content.get(primary.line)?.get(primary.start..primary.end)?;
+            for (r, mirror) in tabstop.ranges.iter().enumerate().skip(1) {
+                let expected = match &tabstop.transforms[r] {
+                    Some(transform) => transform.apply(text),
+                    None => text.to_string(),
+                };
+                let current = content.get(mirror.line).and_then(|l| l.get(mirror.start..mirror.end));
+                if current != Some(expected.as_str()) {
+                    return Some((t, r, expected));
+                }
+            }
+        }
+        None
+    }
+}
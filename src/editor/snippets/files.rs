@@ -0,0 +1,260 @@
+//! Snippets loaded from files in the user's config directory
+//! (`~/.config/vimr/snippets`) and the project's `.vimr/snippets`.
+//!
+//! Three formats are read, by extension:
+//!
+//! - `.toml`: one table per trigger with `body` and optional
+//!   `description`, `headers`, `namespaces` and `requires`
+//! - `.snippets`: UltiSnips `snippet trigger "description"` ...
+//!   `endsnippet` blocks
+//! - `.json`, `.code-snippets`: VS Code snippet files, where `prefix` is the
+//!   trigger (or a list of them) and `body` a string or list of lines
+//!
+//! Files are merged over the built-in snippets in that order of
+//! directories, and by file name within a directory, later definitions
+//! replacing earlier ones.
+
+use crate::editor::config::config_dir;
+use crate::editor::snippets::{default_snippets, Snippet};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+pub const PROJECT_SNIPPETS: &str = ".vimr/snippets";
+
+// How often `changed` looks at the disk.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Directories snippet files are read from, lowest priority first.
+pub fn snippet_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = config_dir().map(|dir| dir.join("snippets")).into_iter().collect();
+    dirs.push(PathBuf::from(PROJECT_SNIPPETS));
+    dirs
+}
+
+fn is_snippet_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("toml" | "snippets" | "json" | "code-snippets")
+    )
+}
+
+fn snippet_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_snippet_file(path))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Parses one snippet file according to its extension.
+pub fn parse_file(path: &Path) -> Result<Vec<(String, Snippet)>, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => parse_toml(&text),
+        Some("snippets") => Ok(parse_ultisnips(&text)),
+        _ => parse_vscode(&text),
+    }
+}
+
+pub fn parse_toml(text: &str) -> Result<Vec<(String, Snippet)>, String> {
+    let table: HashMap<String, Snippet> = toml::from_str(text).map_err(|e| e.message().to_string())?;
+    let mut snippets: Vec<(String, Snippet)> = table.into_iter().collect();
+    snippets.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(snippets)
+}
+
+fn parse_ultisnips(text: &str) -> Vec<(String, Snippet)> {
+    let mut snippets = Vec::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let Some(header) = line.strip_prefix("snippet ") else {
+            continue;
+        };
+        let header = header.trim();
+        let (trigger, rest) = header.split_once(char::is_whitespace).unwrap_or((header, ""));
+        let description = rest
+            .trim()
+            .strip_prefix('"')
+            .and_then(|rest| rest.split_once('"'))
+            .map_or("", |(description, _)| description);
+
+        let body: Vec<&str> = lines.by_ref().take_while(|l| l.trim_end() != "endsnippet").collect();
+        snippets.push((
+            trigger.to_string(),
+            Snippet { description: description.to_string(), body: body.join("\n"), ..Default::default() },
+        ));
+    }
+    snippets
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            OneOrMany::One(s) => vec![s],
+            OneOrMany::Many(v) => v,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct VscodeSnippet {
+    prefix: OneOrMany,
+    body: OneOrMany,
+    #[serde(default)]
+    description: String,
+}
+
+fn parse_vscode(text: &str) -> Result<Vec<(String, Snippet)>, String> {
+    let map: HashMap<String, VscodeSnippet> = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let mut entries: Vec<(String, VscodeSnippet)> = map.into_iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut snippets = Vec::new();
+    for (name, snippet) in entries {
+        let description = if snippet.description.is_empty() { name } else { snippet.description };
+        let body = snippet.body.into_vec().join("\n");
+        for prefix in snippet.prefix.into_vec() {
+            snippets.push((
+                prefix,
+                Snippet { description: description.clone(), body: body.clone(), ..Default::default() },
+            ));
+        }
+    }
+    Ok(snippets)
+}
+
+/// The file snippets created or edited from inside the editor are saved
+/// to.
+pub fn user_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("snippets").join("user.toml"))
+}
+
+fn toml_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        key.to_string()
+    } else {
+        toml::Value::String(key.to_string()).to_string()
+    }
+}
+
+/// A snippet as a table of the native format, with the body as a
+/// multi-line literal string where possible so it reads as written.
+pub fn to_toml(trigger: &str, snippet: &Snippet) -> String {
+    let body = if snippet.body.contains("'''") {
+        toml::Value::String(snippet.body.clone()).to_string()
+    } else {
+        format!("'''\n{}'''", snippet.body)
+    };
+    let mut text = format!(
+        "[{}]\ndescription = {}\n",
+        toml_key(trigger),
+        toml::Value::String(snippet.description.clone())
+    );
+    for (key, list) in [
+        ("headers", &snippet.headers),
+        ("namespaces", &snippet.namespaces),
+        ("requires", &snippet.requires),
+    ] {
+        if !list.is_empty() {
+            let values: Vec<toml::Value> = list.iter().cloned().map(toml::Value::String).collect();
+            text.push_str(&format!("{} = {}\n", key, toml::Value::Array(values)));
+        }
+    }
+    text.push_str(&format!("body = {}\n", body));
+    text
+}
+
+/// Adds or replaces snippets in `user_file`, which is rewritten in
+/// trigger order. Returns its path.
+pub fn save_user_snippets(snippets: Vec<(String, Snippet)>) -> Result<PathBuf, String> {
+    let path = user_file().ok_or("no config directory")?;
+    let mut all: HashMap<String, Snippet> = if path.exists() {
+        parse_file(&path)?.into_iter().collect()
+    } else {
+        HashMap::new()
+    };
+    all.extend(snippets);
+
+    let mut triggers: Vec<&String> = all.keys().collect();
+    triggers.sort();
+    let text: Vec<String> = triggers.iter().map(|t| to_toml(t, &all[*t])).collect();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(&path, text.join("\n")).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+/// Tracks the snippet files on disk so edits to them can be picked up.
+pub struct SnippetFiles {
+    dirs: Vec<PathBuf>,
+    stamp: Vec<(PathBuf, Option<SystemTime>)>,
+    checked: Instant,
+}
+
+impl Default for SnippetFiles {
+    fn default() -> Self {
+        SnippetFiles { dirs: snippet_dirs(), stamp: Vec::new(), checked: Instant::now() }
+    }
+}
+
+impl SnippetFiles {
+    fn current_stamp(&self) -> Vec<(PathBuf, Option<SystemTime>)> {
+        self.dirs
+            .iter()
+            .flat_map(|dir| snippet_files(dir))
+            .map(|path| {
+                let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+                (path, modified)
+            })
+            .collect()
+    }
+
+    /// The built-in snippets with every snippet file merged over them,
+    /// and an error message for each file that could not be read.
+    pub fn load(&mut self) -> (HashMap<String, Snippet>, Vec<String>) {
+        let mut snippets = default_snippets();
+        let mut errors = Vec::new();
+        self.stamp = self.current_stamp();
+        for (path, _) in &self.stamp {
+            match parse_file(path) {
+                Ok(loaded) => {
+                    for (trigger, snippet) in loaded {
+                        if trigger.is_empty() || snippet.body.is_empty() {
+                            errors.push(format!("{}: snippet '{}' has no trigger or body", path.display(), trigger));
+                        } else {
+                            snippets.insert(trigger, snippet);
+                        }
+                    }
+                }
+                Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+        (snippets, errors)
+    }
+
+    /// Whether any snippet file was added, removed or modified since the
+    /// last `load`. Looks at the disk at most once a second.
+    pub fn changed(&mut self) -> bool {
+        if self.checked.elapsed() < CHECK_INTERVAL {
+            return false;
+        }
+        self.checked = Instant::now();
+        self.current_stamp() != self.stamp
+    }
+}
@@ -1,20 +1,139 @@
+use serde::Deserialize;
 use std::collections::HashMap;
 
-pub fn default_snippets() -> HashMap<String, String> {
+pub mod deps;
+pub mod engine;
+pub mod files;
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Snippet {
+    /// Shown next to the trigger when it is suggested.
+    pub description: String,
+    pub body: String,
+    /// Headers the body needs, such as `queue` or `"local.h"`.
+    pub headers: Vec<String>,
+    /// Namespaces the body assumes are open, such as `std`.
+    pub namespaces: Vec<String>,
+    /// Snippets the body builds on, inserted before it when missing.
+    pub requires: Vec<String>,
+}
+
+/// The bundled snippet library, one TOML file per topic.
+const LIBRARY: [(&str, &str); 6] = [
+    ("math", include_str!("library/math.toml")),
+    ("data_structures", include_str!("library/data_structures.toml")),
+    ("graphs", include_str!("library/graphs.toml")),
+    ("strings", include_str!("library/strings.toml")),
+    ("geometry", include_str!("library/geometry.toml")),
+    ("debug", include_str!("library/debug.toml")),
+];
+
+/// Headers each built-in snippet needs. All of them but the fast I/O
+/// templates also assume `using namespace std`.
+const BUILTIN_HEADERS: [(&str, &[&str]); 11] = [
+    ("bubblesort", &["vector", "utility"]),
+    ("mergesort", &["vector"]),
+    ("quicksort", &["vector", "utility"]),
+    ("heapsort", &["vector", "utility"]),
+    ("binarysearch", &["vector"]),
+    ("lowerbound", &["vector"]),
+    ("dfs", &["iostream", "vector"]),
+    ("bfs", &["iostream", "queue", "vector"]),
+    ("dijkstra", &["climits", "queue", "utility", "vector"]),
+    ("dsu", &["utility", "vector"]),
+    ("segtree", &["vector"]),
+];
+
+/// Indentation width snippet bodies are written in; a tab counts as one
+/// level too.
+const BODY_INDENT: usize = 4;
+
+/// Re-indents the lines of a snippet body after the first to sit under a
+/// trigger line indented by `base`, with each body indent level becoming
+/// one `unit`. Alignment spaces beyond whole levels are kept, and blank
+/// lines are left empty.
+pub fn reindent(body: &str, base: &str, unit: &str) -> String {
+    let mut lines = body.split('\n');
+    let mut out = lines.next().unwrap_or("").to_string();
+    for line in lines {
+        out.push('\n');
+        let text = line.trim_start_matches([' ', '\t']);
+        if text.is_empty() {
+            continue;
+        }
+        let width: usize = line[..line.len() - text.len()]
+            .chars()
+            .map(|c| if c == '\t' { BODY_INDENT } else { 1 })
+            .sum();
+        out.push_str(base);
+        out.push_str(&unit.repeat(width / BODY_INDENT));
+        out.push_str(&" ".repeat(width % BODY_INDENT));
+        out.push_str(text);
+    }
+    out
+}
+
+/// Turns buffer text into a snippet body: the common indent is removed,
+/// the rest is rewritten in the body's four-space levels and `$` is
+/// escaped.
+pub fn body_from_text(lines: &[String], tabstop: usize, shiftwidth: usize) -> String {
+    let width = |line: &str| -> usize {
+        line.chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .fold(0, |col, c| if c == '\t' { col + tabstop - col % tabstop } else { col + 1 })
+    };
+    let common = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| width(line))
+        .min()
+        .unwrap_or(0);
+
+    let body: Vec<String> = lines
+        .iter()
+        .map(|line| {
+            let text = line.trim_start_matches([' ', '\t']).replace('$', "\\$");
+            if text.is_empty() {
+                return text;
+            }
+            let indent = width(line) - common;
+            format!(
+                "{}{}{}",
+                " ".repeat(BODY_INDENT * (indent / shiftwidth)),
+                " ".repeat(indent % shiftwidth),
+                text
+            )
+        })
+        .collect();
+    body.join("\n")
+}
+
+/// Built-in snippets. Bodies use the placeholder syntax described in
+/// `engine`.
+pub fn default_snippets() -> HashMap<String, Snippet> {
     let mut snippets = HashMap::new();
-    
-    snippets.insert("fio1".to_string(), 
+    let mut add = |name: &str, description: &str, body: &str| {
+        snippets.insert(
+            name.to_string(),
+            Snippet { description: description.to_string(), body: body.to_string(), ..Default::default() },
+        );
+    };
+
+    add("fio1", "Basic fast I/O setup",
 r#"#include <bits/stdc++.h>
 using namespace std;
 
 int main() {
     ios_base::sync_with_stdio(false);
     cin.tie(nullptr);
-    
+    $0
     return 0;
-}"#.to_string());
+}"#);
 
-    snippets.insert("fio2".to_string(),
+    add("fio2", "Buffered input optimization",
 r#"#include <bits/stdc++.h>
 using namespace std;
 
@@ -48,11 +167,11 @@ inline int read_int() {
 }
 
 int main() {
-    
+    $0
     return 0;
-}"#.to_string());
+}"#);
 
-    snippets.insert("fio3".to_string(),
+    add("fio3", "Complete FastIO class implementation",
 r#"#include <bits/stdc++.h>
 using namespace std;
 
@@ -122,24 +241,24 @@ public:
 } io;
 
 int main() {
-    
+    $0
     return 0;
-}"#.to_string());
+}"#);
 
     // Sorting Algorithms
-    snippets.insert("bubblesort".to_string(),
-r#"void bubbleSort(vector<int>& arr) {
-    int n = arr.size();
+    add("bubblesort", "Bubble Sort implementation",
+r#"void bubbleSort(vector<${1|int,long long|}>& ${2:arr}) {
+    int n = $2.size();
     for(int i = 0; i < n-1; i++) {
         for(int j = 0; j < n-i-1; j++) {
-            if(arr[j] > arr[j+1]) {
-                swap(arr[j], arr[j+1]);
+            if($2[j] > $2[j+1]) {
+                swap($2[j], $2[j+1]);
             }
         }
     }
-}"#.to_string());
+}"#);
 
-    snippets.insert("mergesort".to_string(),
+    add("mergesort", "Merge Sort implementation",
 r#"void merge(vector<int>& arr, int l, int m, int r) {
     int n1 = m - l + 1;
     int n2 = r - m;
@@ -163,9 +282,9 @@ void mergeSort(vector<int>& arr, int l, int r) {
     mergeSort(arr, l, m);
     mergeSort(arr, m + 1, r);
     merge(arr, l, m, r);
-}"#.to_string());
+}"#);
 
-    snippets.insert("quicksort".to_string(),
+    add("quicksort", "Quick Sort implementation",
 r#"int partition(vector<int>& arr, int low, int high) {
     int pivot = arr[high];
     int i = low - 1;
@@ -186,9 +305,9 @@ void quickSort(vector<int>& arr, int low, int high) {
         quickSort(arr, low, pi - 1);
         quickSort(arr, pi + 1, high);
     }
-}"#.to_string());
+}"#);
 
-    snippets.insert("heapsort".to_string(),
+    add("heapsort", "Heap Sort implementation",
 r#"void heapify(vector<int>& arr, int n, int i) {
     int largest = i;
     int l = 2 * i + 1;
@@ -212,48 +331,48 @@ void heapSort(vector<int>& arr) {
         swap(arr[0], arr[i]);
         heapify(arr, i, 0);
     }
-}"#.to_string());
+}"#);
 
     // Searching Algorithms
-    snippets.insert("binarysearch".to_string(),
-r#"int binarySearch(vector<int>& arr, int target) {
+    add("binarysearch", "Binary Search implementation",
+r#"int binarySearch(vector<${1|int,long long|}>& arr, $1 ${2:target}) {
     int left = 0, right = arr.size() - 1;
     
     while(left <= right) {
         int mid = left + (right - left) / 2;
-        if(arr[mid] == target) return mid;
-        if(arr[mid] < target) left = mid + 1;
+        if(arr[mid] == $2) return mid;
+        if(arr[mid] < $2) left = mid + 1;
         else right = mid - 1;
     }
     return -1;  // Not found
-}"#.to_string());
+}"#);
 
-    snippets.insert("lowerbound".to_string(),
-r#"int lowerBound(vector<int>& arr, int target) {
+    add("lowerbound", "Lower Bound implementation",
+r#"int lowerBound(vector<${1|int,long long|}>& arr, $1 ${2:target}) {
     int left = 0, right = arr.size();
     
     while(left < right) {
         int mid = left + (right - left) / 2;
-        if(arr[mid] < target) left = mid + 1;
+        if(arr[mid] < $2) left = mid + 1;
         else right = mid;
     }
     return left;
-}"#.to_string());
+}"#);
 
     // Graph Algorithms
-    snippets.insert("dfs".to_string(),
+    add("dfs", "Depth First Search",
 r#"void dfs(vector<vector<int>>& graph, int v, vector<bool>& visited) {
     visited[v] = true;
-    cout << v << " ";
+    ${1:cout << v << " ";}
     
     for(int u : graph[v]) {
         if(!visited[u]) {
             dfs(graph, u, visited);
         }
     }
-}"#.to_string());
+}"#);
 
-    snippets.insert("bfs".to_string(),
+    add("bfs", "Breadth First Search",
 r#"void bfs(vector<vector<int>>& graph, int start) {
     int n = graph.size();
     vector<bool> visited(n, false);
@@ -274,9 +393,9 @@ r#"void bfs(vector<vector<int>>& graph, int start) {
             }
         }
     }
-}"#.to_string());
+}"#);
 
-    snippets.insert("dijkstra".to_string(),
+    add("dijkstra", "Dijkstra's Shortest Path",
 r#"vector<int> dijkstra(vector<vector<pair<int,int>>>& graph, int start) {
     int n = graph.size();
     vector<int> dist(n, INT_MAX);
@@ -300,10 +419,10 @@ r#"vector<int> dijkstra(vector<vector<pair<int,int>>>& graph, int start) {
         }
     }
     return dist;
-}"#.to_string());
+}"#);
 
     // Data Structures
-    snippets.insert("dsu".to_string(),
+    add("dsu", "Disjoint Set Union implementation",
 r#"class DSU {
     vector<int> parent, rank;
 public:
@@ -323,9 +442,9 @@ public:
         parent[py] = px;
         if(rank[px] == rank[py]) rank[px]++;
     }
-}"#.to_string());
+};"#);
 
-    snippets.insert("segtree".to_string(),
+    add("segtree", "Segment Tree implementation",
 r#"class SegTree {
     vector<int> tree;
     int n;
@@ -369,7 +488,17 @@ private:
         return query(2*node+1, start, mid, l, r) +
                query(2*node+2, mid+1, end, l, r);
     }
-}"#.to_string());
+};"#);
 
+    for (name, headers) in BUILTIN_HEADERS {
+        if let Some(snippet) = snippets.get_mut(name) {
+            snippet.headers = headers.iter().map(|h| h.to_string()).collect();
+            snippet.namespaces = vec!["std".to_string()];
+        }
+    }
+    for (name, text) in LIBRARY {
+        let library = files::parse_toml(text).unwrap_or_else(|e| panic!("snippet library {}: {}", name, e));
+        snippets.extend(library);
+    }
     snippets
 }
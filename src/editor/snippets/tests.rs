@@ -0,0 +1,362 @@
+use super::engine::{self, Expansion, Session};
+use super::files;
+use super::{body_from_text, default_snippets, deps, reindent, Snippet};
+use std::collections::HashMap;
+use std::fs;
+
+/// The tabstops in jump order, as `$1 0:9-10 0:16-17; $0 1:1-1`: each
+/// number with its ranges as `line:start-end`.
+fn tabstops(expansion: &Expansion) -> String {
+    let tabstops: Vec<String> = expansion
+        .tabstops
+        .iter()
+        .map(|t| {
+            let ranges = t.ranges.iter().map(|r| format!(" {}:{}-{}", r.line, r.start, r.end));
+            format!("${}{}", t.index, ranges.collect::<String>())
+        })
+        .collect();
+    tabstops.join("; ")
+}
+
+fn expand(body: &str) -> Expansion {
+    engine::expand(body, &|_| None)
+}
+
+#[test]
+fn parses_tabstops() {
+    let cases = [
+        // A missing `$0` goes at the end.
+        ("a$1b", "ab", "$1 0:1-1; $0 0:2-2"),
+        ("${1}x${2:y}", "xy", "$1 0:0-0; $2 0:1-2; $0 0:2-2"),
+        // `$0` comes last wherever it is, and jumps go by number.
+        ("$0 $2 $1", "  ", "$1 0:2-2; $2 0:1-1; $0 0:0-0"),
+        ("if ($1) {\n\t$0\n}", "if () {\n\t\n}", "$1 0:4-4; $0 1:1-1"),
+        // Placeholders nest, each with its own range.
+        ("${1:pair<${2:int}, ${3:int}>} p;", "pair<int, int> p;", "$1 0:0-14; $2 0:5-8; $3 0:10-13; $0 0:17-17"),
+        // Later uses of a number mirror the first, default included.
+        (
+            "for (int ${1:i} = 0; $1 < ${2:n}; $1++)",
+            "for (int i = 0; i < n; i++)",
+            "$1 0:9-10 0:16-17 0:23-24; $2 0:20-21; $0 0:27-27",
+        ),
+        ("$1 ${1:v}", "v v", "$1 0:0-1 0:2-3; $0 0:3-3"),
+    ];
+    for (body, text, expected) in cases {
+        let expansion = expand(body);
+        assert_eq!(expansion.lines.join("\n"), text, "{}", body);
+        assert_eq!(tabstops(&expansion), expected, "{}", body);
+    }
+}
+
+#[test]
+fn parses_escapes_and_literals() {
+    let cases = [
+        ("cost: \\$${1:5} \\}", "cost: $5 }"),
+        // Other backslashes stay, so C++ escapes need no quoting.
+        ("printf(\"%d\\n\", $1);", "printf(\"%d\\n\", );"),
+        ("${1:a\\}b}", "a}b"),
+        // A `$` that starts no tabstop or variable is text, as is a stray `}`.
+        ("$ 5 ${x} $}", "$ 5 ${x} $}"),
+        ("a}b", "a}b"),
+    ];
+    for (body, text) in cases {
+        assert_eq!(expand(body).lines.join("\n"), text, "{}", body);
+    }
+}
+
+#[test]
+fn parses_choices() {
+    let expansion = expand("${1|int,long long,unsigned\\,x|} v;");
+    assert_eq!(expansion.lines, vec!["int v;"]);
+    assert_eq!(expansion.tabstops[0].choices, vec!["int", "long long", "unsigned,x"]);
+    assert_eq!(tabstops(&expansion), "$1 0:0-3; $0 0:6-6");
+}
+
+#[test]
+fn transforms_mirrors() {
+    let cases = [
+        ("${1:name} ${1/(.*)/${1:/upcase}/}", "name NAME"),
+        ("${1:ABC} ${1/(.*)/${1:/downcase}/}", "ABC abc"),
+        ("${1:x} ${1/(.*)/${1:/capitalize}/}", "x X"),
+        ("${1:my var} ${1/(.*)/${1:/camelcase}/} ${1/(.*)/${1:/pascalcase}/}", "my var myVar MyVar"),
+        // Conditionals pick text by whether the group matched something.
+        ("${1:n} ${1/(.+)/${1:?has:none}/}", "n has"),
+        ("${1}${1/(.+)?/${1:-none}/}", "none"),
+        ("${1:v}${1/(.+)/${1:+, }/}", "v, "),
+        // Flags, and an escaped `/` in the regex.
+        ("${1:a-b-c} ${1/-/_/}", "a-b-c a_b-c"),
+        ("${1:a-b-c} ${1/-/_/g}", "a-b-c a_b_c"),
+        ("${1:Foo} ${1/foo/bar/i}", "Foo bar"),
+        ("${1:a/b} ${1/\\//::/g}", "a/b a::b"),
+    ];
+    for (body, text) in cases {
+        assert_eq!(expand(body).lines.join("\n"), text, "{}", body);
+    }
+
+    // A tabstop that only appears transformed cannot be edited.
+    let expansion = expand("${1/(.*)/x/}");
+    assert_eq!(expansion.lines, vec!["x"]);
+    assert_eq!(tabstops(&expansion), "$0 0:1-1");
+}
+
+#[test]
+fn transformed_mirrors_follow_edits() {
+    let expansion = expand("${1:ab} ${1/(.*)/${1:/upcase}/}");
+    assert_eq!(tabstops(&expansion), "$1 0:0-2 0:3-5; $0 0:5-5");
+    let session = Session::new(expansion, 0, 0);
+    assert!(session.stale_mirror(&["ab AB".to_string()]).is_none());
+    let stale = session.stale_mirror(&["xy AB".to_string()]);
+    assert_eq!(stale, Some((0, 1, "XY".to_string())));
+}
+
+#[test]
+fn resolves_variables() {
+    let resolve = |name: &str| match name {
+        "TM_FILENAME" => Some("main.cpp".to_string()),
+        "EMPTY" => Some(String::new()),
+        _ => None,
+    };
+    let cases = [
+        ("$TM_FILENAME", "main.cpp"),
+        ("${TM_FILENAME/(.*)\\.cpp/${1:/upcase}_H/}", "MAIN_H"),
+        // Unknown and empty variables fall back to their default, or stay
+        // as written.
+        ("$UNKNOWN x", "$UNKNOWN x"),
+        ("${UNKNOWN}", "$UNKNOWN"),
+        ("${UNKNOWN:fallback}", "fallback"),
+        ("${EMPTY:fallback}", "fallback"),
+        ("${UNKNOWN:${1:x}}", "x"),
+        ("${UNKNOWN/(.*)/${1:/upcase}/}", "$UNKNOWN"),
+        // Names are upper case, so this is text.
+        ("$foo", "$foo"),
+    ];
+    for (body, text) in cases {
+        assert_eq!(engine::expand(body, &resolve).lines.join("\n"), text, "{}", body);
+    }
+}
+
+#[test]
+fn reindents_bodies() {
+    let body = "for (;;) {\n    if (x) {\n        y();\n    }\n\n    z(a,\n      b);\n}";
+    // Levels become the unit, under the trigger line's indent; the first
+    // line is left to the trigger's position.
+    assert_eq!(
+        reindent(body, "\t", "\t"),
+        "for (;;) {\n\t\tif (x) {\n\t\t\ty();\n\t\t}\n\n\t\tz(a,\n\t\t  b);\n\t}"
+    );
+    assert_eq!(reindent(body, "  ", "  "), "for (;;) {\n    if (x) {\n      y();\n    }\n\n    z(a,\n      b);\n  }");
+    // Tabs in the body are a level each, and blank lines stay empty
+    // whatever spaces they had.
+    assert_eq!(reindent("a\n\tb\n\t\tc\n \t \nd", "", "  "), "a\n  b\n    c\n\nd");
+    assert_eq!(reindent("single line", "    ", "\t"), "single line");
+}
+
+#[test]
+fn turns_text_into_bodies() {
+    let lines = |text: &str| text.split('\n').map(String::from).collect::<Vec<_>>();
+    // With tabs of 8 and a shiftwidth of 4 a tab is two levels.
+    assert_eq!(
+        body_from_text(&lines("\tif (x) {\n\t    y = $1;\n\n\t}"), 8, 4),
+        "if (x) {\n    y = \\$1;\n\n}"
+    );
+    assert_eq!(body_from_text(&lines("\tif (x) {\n\t\ty();\n\t}"), 8, 4), "if (x) {\n        y();\n}");
+    // With tabs of 4 and a shiftwidth of 2, levels of two columns become
+    // four spaces; odd columns are kept as alignment. A tab after spaces
+    // goes to the next tab stop.
+    assert_eq!(
+        body_from_text(&lines("  a\n\tb\n\t  c\n   d\n  \te"), 4, 2),
+        "a\n    b\n        c\n d\n    e"
+    );
+    // Blank lines don't count towards the common indent.
+    assert_eq!(body_from_text(&lines("    a\n\n  \n      b"), 4, 4), "a\n\n\n  b");
+}
+
+/// Parses `text` as a snippet file named `name`, which picks the format.
+fn parse(name: &str, text: &str) -> Result<Vec<(String, Snippet)>, String> {
+    let dir = std::env::temp_dir().join(format!("vimr-snippets-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, text).unwrap();
+    let parsed = files::parse_file(&path);
+    fs::remove_file(&path).ok();
+    parsed
+}
+
+/// Trigger, description and body of each snippet.
+fn summary(snippets: &[(String, Snippet)]) -> Vec<(&str, &str, &str)> {
+    snippets.iter().map(|(t, s)| (t.as_str(), s.description.as_str(), s.body.as_str())).collect()
+}
+
+#[test]
+fn parses_toml_files() {
+    let text = r#"
+[fori]
+description = "Counting loop"
+headers = ["vector"]
+requires = ["io"]
+body = '''
+for (int ${1:i} = 0; $1 < ${2:n}; $1++) {
+    $0
+}'''
+
+["c++"]
+body = "// $0"
+"#;
+    let snippets = parse("loops.toml", text).unwrap();
+    assert_eq!(
+        summary(&snippets),
+        vec![("c++", "", "// $0"), ("fori", "Counting loop", "for (int ${1:i} = 0; $1 < ${2:n}; $1++) {\n    $0\n}")]
+    );
+    assert_eq!(snippets[1].1.headers, vec!["vector"]);
+    assert_eq!(snippets[1].1.requires, vec!["io"]);
+    assert!(parse("bad.toml", "[fori]\nbody = ").is_err());
+    assert!(parse("bad.toml", "fori = 3").is_err());
+}
+
+#[test]
+fn parses_ultisnips_files() {
+    let text = r#"priority -50
+# A comment
+snippet fori "Counting loop" b
+for (int ${1:i} = 0; $1 < ${2:n}; $1++) {
+	$0
+}
+endsnippet
+
+snippet pb
+push_back(${1});
+endsnippet
+
+snippet cout "Print to stdout" iw
+cout << $1;
+endsnippet
+snippet unfinished "Runs to the end"
+a
+"#;
+    assert_eq!(
+        summary(&parse("cpp.snippets", text).unwrap()),
+        vec![
+            ("fori", "Counting loop", "for (int ${1:i} = 0; $1 < ${2:n}; $1++) {\n\t$0\n}"),
+            ("pb", "", "push_back(${1});"),
+            ("cout", "Print to stdout", "cout << $1;"),
+            ("unfinished", "Runs to the end", "a"),
+        ]
+    );
+}
+
+#[test]
+fn parses_vscode_files() {
+    let text = r#"{
+    "For loop": {
+        "prefix": ["fori", "for"],
+        "body": ["for (int ${1:i} = 0; $1 < ${2:n}; $1++) {", "\t$0", "}"],
+        "description": "Counting loop"
+    },
+    "Push back": {
+        "prefix": "pb",
+        "body": "push_back($1);"
+    }
+}"#;
+    let for_body = "for (int ${1:i} = 0; $1 < ${2:n}; $1++) {\n\t$0\n}";
+    assert_eq!(
+        summary(&parse("cpp.json", text).unwrap()),
+        vec![("fori", "Counting loop", for_body), ("for", "Counting loop", for_body), ("pb", "Push back", "push_back($1);")]
+    );
+    assert!(parse("cpp.code-snippets", text).is_ok());
+    let bad = ["", "{", "[]", r#"{"x": {"body": "a"}}"#, r#"{"x": {"prefix": 1, "body": "a"}}"#, r#"{"x": {"prefix": "x", "body": [1]}}"#];
+    for text in bad {
+        assert!(parse("bad.json", text).is_err(), "{}", text);
+    }
+}
+
+#[test]
+fn library_requirements_resolve() {
+    let snippets = default_snippets();
+    for (trigger, snippet) in &snippets {
+        for required in &snippet.requires {
+            assert!(snippets.contains_key(required), "{} requires missing snippet {}", trigger, required);
+        }
+        assert!(!snippet.body.is_empty(), "{} has no body", trigger);
+    }
+    let requires = |trigger: &str| snippets[trigger].requires.clone();
+    assert_eq!(requires("lazysegtree"), vec!["modint"]);
+    assert_eq!(requires("ncr"), vec!["modint"]);
+    assert_eq!(requires("kruskal"), vec!["dsu"]);
+    assert_eq!(requires("convexhull"), vec!["geometry"]);
+}
+
+fn requirement_snippets() -> HashMap<String, Snippet> {
+    let snippet = |body: &str, headers: &[&str], namespaces: &[&str], requires: &[&str]| Snippet {
+        body: body.to_string(),
+        headers: headers.iter().map(|h| h.to_string()).collect(),
+        namespaces: namespaces.iter().map(|n| n.to_string()).collect(),
+        requires: requires.iter().map(|r| r.to_string()).collect(),
+        ..Default::default()
+    };
+    HashMap::from([
+        ("modint".to_string(), snippet("struct mint {\n    int v;\n};\n$0", &[], &[], &[])),
+        ("power".to_string(), snippet("template <typename T>\nT power(T a, long long e) {\n    return a;\n}", &[], &[], &["modint"])),
+        (
+            "ncr".to_string(),
+            snippet("mint ncr(int n, int k) {\n    return ${0:power(n, k)};\n}", &["vector", "\"local.h\""], &["std"], &["power", "nosuch"]),
+        ),
+    ])
+}
+
+#[test]
+fn finds_missing_requirements() {
+    let snippets = requirement_snippets();
+    let requirements = |content: &str| {
+        let content: Vec<String> = content.lines().map(String::from).collect();
+        let found = deps::requirements("ncr", &snippets, &content);
+        (found.preamble, found.snippets)
+    };
+    // Dependencies come first, each once; unknown ones are skipped.
+    assert_eq!(
+        requirements(""),
+        (
+            vec!["#include <vector>".to_string(), "#include \"local.h\"".to_string(), "using namespace std;".to_string()],
+            vec!["modint".to_string(), "power".to_string()],
+        )
+    );
+    // Includes and namespaces already there, however spaced, are not
+    // added again; `bits/stdc++.h` stands for every system header.
+    let present = "#include <vector>\n  #  include \"local.h\" // ours\nusing namespace std;\n";
+    assert_eq!(requirements(present).0, Vec::<String>::new());
+    assert_eq!(requirements("#include <bits/stdc++.h>\nusing namespace std;").0, vec!["#include \"local.h\""]);
+    assert_eq!(requirements("using namespace std ;").0.last().map(String::as_str), Some("using namespace std;"));
+    // A snippet is present when the line opening its first definition is,
+    // ignoring spacing; what it requires is then not needed either.
+    assert_eq!(requirements("struct  mint{\n};").1, vec!["power"]);
+    assert_eq!(requirements("template <typename T>\nT power(T a, long long e) {\n}").1, Vec::<String>::new());
+    // Only the line that opens the definition counts.
+    assert_eq!(requirements("    int v;\n").1, vec!["modint", "power"]);
+}
+
+#[test]
+fn places_requirements() {
+    let content: Vec<String> =
+        "#include <vector>\nusing namespace std;\n\ntemplate <typename T>\nvoid f() {\n    int x;\n\n    ncr\n}\n\nncr"
+            .lines()
+            .map(String::from)
+            .collect();
+    // Includes go after the last one, before `using namespace`.
+    assert_eq!(deps::include_line(&content), 1);
+    assert_eq!(deps::include_line(&["int main() {}".to_string()]), 0);
+    // Snippets go above the definition the cursor is in, with its
+    // template line, or above the cursor line at the top level; either
+    // way after the include and using block.
+    assert_eq!(deps::insertion_line(&content, 7), 3);
+    assert_eq!(deps::insertion_line(&content, 10), 10);
+    assert_eq!(deps::insertion_line(&content, 2), 2);
+}
+
+#[test]
+fn builtin_classes_are_closed() {
+    // Required snippets go above other code, so a class body without its
+    // `;` breaks whatever follows it.
+    let snippets = default_snippets();
+    for trigger in ["dsu", "segtree"] {
+        assert!(snippets[trigger].body.trim_end().ends_with("};"), "{}", trigger);
+    }
+}
@@ -0,0 +1,196 @@
+use chrono::Local;
+use std::path::Path;
+
+pub const DEFAULT_FORMAT: &str = " %M | %f%m %y%=%S %r %b %d  %e [%o]  %l:%c  %P ";
+
+/// Everything a status line format can refer to.
+pub struct StatusInfo<'a> {
+    pub mode: &'a str,
+    pub filename: Option<&'a str>,
+    pub modified: bool,
+    pub encoding: &'a str,
+    pub fileformat: &'a str,
+    pub line: usize,
+    pub col: usize,
+    pub line_count: usize,
+    pub top: usize,
+    pub bottom: usize,
+    pub pending: String,
+    pub recording: Option<char>,
+    pub build: Option<&'a str>,
+    /// Errors and warnings the language server reports.
+    pub diagnostics: (usize, usize),
+}
+
+/// Filetype name derived from a file's extension.
+pub fn filetype(filename: &str) -> &'static str {
+    let extension = Path::new(filename).extension().and_then(|e| e.to_str()).unwrap_or("");
+    match extension {
+        "cpp" | "cc" | "cxx" | "hpp" | "hh" | "hxx" | "h" => "cpp",
+        "c" => "c",
+        "rs" => "rust",
+        "py" => "python",
+        "toml" => "toml",
+        "md" => "markdown",
+        "txt" | "in" | "out" | "ans" => "text",
+        _ => "",
+    }
+}
+
+fn position(info: &StatusInfo) -> String {
+    if info.top == 0 && info.bottom + 1 >= info.line_count {
+        "All".to_string()
+    } else if info.top == 0 {
+        "Top".to_string()
+    } else if info.bottom + 1 >= info.line_count {
+        "Bot".to_string()
+    } else {
+        format!("{}%", info.top * 100 / (info.line_count - (info.bottom - info.top + 1)).max(1))
+    }
+}
+
+/// Expands a status line format string:
+///
+/// `%M` mode, `%f` file name, `%t` file name without directories,
+/// `%m` modified flag, `%y` filetype in brackets, `%Y` bare filetype,
+/// `%e` encoding, `%o` file format, `%l` line, `%c` column, `%L` line
+/// count, `%p` percentage through the file, `%P` Top/Bot/All or the
+/// percentage of the view, `%S` pending keys, `%r` macro recording,
+/// `%b` build status, `%d` error and warning counts, `%T` time, `%=` start of the right aligned part,
+/// `%<` where to cut the line if it is too long and `%%` a literal percent sign.
+///
+/// Items that have nothing to show expand to an empty string. A line too
+/// long for `width` loses text after `%<`, marked with `<`; without one
+/// the end of the left part goes.
+pub fn render(format: &str, info: &StatusInfo, width: usize) -> String {
+    let mut left = String::new();
+    let mut right = String::new();
+    let mut aligned = false;
+    // Where `%<` was, in characters, and whether it was in the right part.
+    let mut cut = None;
+
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        let out = if aligned { &mut right } else { &mut left };
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let name = info.filename.unwrap_or("[No Name]");
+        match chars.next() {
+            Some('M') => out.push_str(info.mode),
+            Some('f') => out.push_str(name),
+            Some('t') => out.push_str(Path::new(name).file_name().and_then(|n| n.to_str()).unwrap_or(name)),
+            Some('m') if info.modified => out.push_str(" [+]"),
+            Some('y') => {
+                let filetype = info.filename.map(filetype).unwrap_or("");
+                if !filetype.is_empty() {
+                    out.push_str(&format!("[{}]", filetype));
+                }
+            }
+            Some('Y') => out.push_str(info.filename.map(filetype).unwrap_or("")),
+            Some('e') => out.push_str(info.encoding),
+            Some('o') => out.push_str(info.fileformat),
+            Some('l') => out.push_str(&(info.line + 1).to_string()),
+            Some('c') => out.push_str(&(info.col + 1).to_string()),
+            Some('L') => out.push_str(&info.line_count.to_string()),
+            Some('p') => out.push_str(&((info.line + 1) * 100 / info.line_count.max(1)).to_string()),
+            Some('P') => out.push_str(&position(info)),
+            Some('S') => out.push_str(&info.pending),
+            Some('r') => {
+                if let Some(register) = info.recording {
+                    out.push_str(&format!("recording @{}", register));
+                }
+            }
+            Some('b') => out.push_str(info.build.unwrap_or("")),
+            Some('d') => {
+                let (errors, warnings) = info.diagnostics;
+                let counts = [(errors, 'E'), (warnings, 'W')].map(|(n, c)| if n > 0 { format!("{}{}", c, n) } else { String::new() });
+                out.push_str(counts.join(" ").trim());
+            }
+            Some('T') => out.push_str(&Local::now().format("%H:%M:%S").to_string()),
+            Some('=') => aligned = true,
+            Some('<') => cut = Some((aligned, out.chars().count())),
+            Some('%') => out.push('%'),
+            _ => {}
+        }
+    }
+
+    let used = left.chars().count() + right.chars().count();
+    if used < width {
+        left.push_str(&" ".repeat(width - used));
+        left.push_str(&right);
+        left
+    } else if let Some((in_right, at)) = cut {
+        let at = if in_right { left.chars().count() + at } else { at };
+        let line: Vec<char> = left.chars().chain(right.chars()).collect();
+        let end = at + (used - width) + 1;
+        if end <= line.len() {
+            return line[..at].iter().chain(['<'].iter()).chain(line[end..].iter()).collect();
+        }
+        line.into_iter().take(width).collect()
+    } else {
+        // Too narrow: keep the right part and truncate the left one.
+        let keep = width.saturating_sub(right.chars().count());
+        left.chars().take(keep).chain(right.chars()).take(width).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> StatusInfo<'static> {
+        StatusInfo {
+            mode: "NORMAL",
+            filename: Some("src/main.cpp"),
+            modified: true,
+            encoding: "utf-8",
+            fileformat: "unix",
+            line: 9,
+            col: 4,
+            line_count: 40,
+            top: 0,
+            bottom: 19,
+            pending: String::new(),
+            recording: None,
+            build: None,
+            diagnostics: (0, 0),
+        }
+    }
+
+    #[test]
+    fn expands_items() {
+        let info = info();
+        assert_eq!(render("%f%m %l:%c %p%%", &info, 30), "src/main.cpp [+] 10:5 25%     ");
+        assert_eq!(render("%t %y %Y %e %o %L %P", &info, 36), "main.cpp [cpp] cpp utf-8 unix 40 Top");
+        let saved = StatusInfo { modified: false, filename: None, ..info };
+        assert_eq!(render("%f%m|%y|", &saved, 12), "[No Name]|| ");
+        let busy = StatusInfo { recording: Some('a'), build: Some("make: ok"), diagnostics: (2, 1), pending: "2d".to_string(), ..self::info() };
+        assert_eq!(render("%r|%b|%d|%S", &busy, 32), "recording @a|make: ok|E2 W1|2d  ");
+        assert_eq!(render("%d|", &self::info(), 1), "|");
+    }
+
+    #[test]
+    fn aligns_the_right_part() {
+        let info = info();
+        assert_eq!(render("%f%=%l:%c", &info, 20), "src/main.cpp    10:5");
+        assert_eq!(render("%=%l", &info, 5), "   10");
+        assert_eq!(render("a%=b%=c", &info, 5), "a  bc");
+    }
+
+    #[test]
+    fn truncates_long_lines() {
+        let info = info();
+        // Without `%<` the end of the left part goes.
+        assert_eq!(render("%f%m%=%l:%c", &info, 10), "src/ma10:5");
+        assert_eq!(render("%f%=%l:%c", &info, 3), "10:");
+        // With it, the text after it, marked with `<`.
+        assert_eq!(render("%<%f%m%=%l:%c", &info, 12), "<cpp [+]10:5");
+        assert_eq!(render("NORMAL %<%f%=%l:%c", &info, 16), "NORMAL <.cpp10:5");
+        assert_eq!(render("%f %=%<%l:%c %M", &info, 16), "src/main.cpp <AL");
+        // Nothing to cut after it: the line is cut at the width.
+        assert_eq!(render("%f%<", &info, 5), "src/m");
+        assert_eq!(render("%<%f", &info, 20), "src/main.cpp        ");
+    }
+}
@@ -0,0 +1,225 @@
+/// The part of the buffer shown on screen. `top` is the first visible
+/// line; `left` is the first visible display column when lines are not
+/// wrapped. Unlike recomputing the scroll position from the cursor on every
+/// frame, the viewport only moves when the cursor would leave it.
+#[derive(Clone, Copy, Default)]
+pub struct Viewport {
+    pub top: usize,
+    pub left: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+/// One screen row of a wrapped line, as a range of display columns.
+#[derive(Clone, Copy)]
+pub struct Segment {
+    pub start: usize,
+    pub end: usize,
+}
+
+// Characters after which `linebreak` may break a line, as vim's 'breakat'.
+const BREAKAT: &[char] = &[' ', '\t', '!', '@', '*', '-', '+', ';', ':', ',', '.', '/', '?'];
+
+/// Splits a tab-expanded line into screen rows of at most `width` columns.
+/// Rows after the first lose `showbreak` columns to the showbreak marker.
+/// With `linebreak`, rows end after a `BREAKAT` character where possible
+/// instead of in the middle of a word.
+pub fn wrap_line(line: &[char], width: usize, linebreak: bool, showbreak: usize) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    loop {
+        let avail = if segments.is_empty() { width } else { width.saturating_sub(showbreak) }.max(1);
+        if line.len() - start <= avail {
+            segments.push(Segment { start, end: line.len() });
+            return segments;
+        }
+        let mut end = start + avail;
+        if linebreak {
+            if let Some(pos) = line[start..end].iter().rposition(|c| BREAKAT.contains(c)) {
+                if pos > 0 {
+                    end = start + pos + 1;
+                }
+            }
+        }
+        segments.push(Segment { start, end });
+        start = end;
+    }
+}
+
+impl Viewport {
+    fn scrolloff(&self, scrolloff: usize) -> usize {
+        scrolloff.min(self.height.saturating_sub(1) / 2)
+    }
+
+    /// Last line that fits on screen, where `rows(i)` is the number of
+    /// screen rows line `i` occupies.
+    pub fn bottom(&self, line_count: usize, rows: impl Fn(usize) -> usize) -> usize {
+        let mut used = 0;
+        let mut line = self.top;
+        while line < line_count {
+            used += rows(line);
+            if used > self.height {
+                break;
+            }
+            line += 1;
+        }
+        line.saturating_sub(1).max(self.top)
+    }
+
+    /// Scrolls vertically so `line` is visible with `scrolloff` lines of
+    /// context above and below it.
+    pub fn follow_line(&mut self, line: usize, line_count: usize, scrolloff: usize, rows: impl Fn(usize) -> usize) {
+        let so = self.scrolloff(scrolloff);
+        self.top = self.top.min(line.saturating_sub(so));
+
+        let last = (line + so).min(line_count.saturating_sub(1));
+        while self.top < line && (self.top..=last).map(&rows).sum::<usize>() > self.height {
+            self.top += 1;
+        }
+    }
+
+    /// Scrolls horizontally so display column `col` is visible with
+    /// `sidescrolloff` columns on either side.
+    pub fn follow_col(&mut self, col: usize, sidescrolloff: usize) {
+        let sso = sidescrolloff.min(self.width.saturating_sub(1) / 2);
+        if col < self.left + sso {
+            self.left = col.saturating_sub(sso);
+        } else if col + sso >= self.left + self.width {
+            self.left = col + sso + 1 - self.width;
+        }
+    }
+
+    /// Moves `top` by `delta` lines, as Ctrl-e and Ctrl-y do.
+    pub fn scroll(&mut self, delta: isize, line_count: usize) {
+        self.top = self.top.saturating_add_signed(delta).min(line_count.saturating_sub(1));
+    }
+
+    /// Clamps `line` into the visible range, honouring `scrolloff`, for
+    /// commands that scroll the view and drag the cursor along.
+    pub fn clamp_line(&self, line: usize, line_count: usize, scrolloff: usize, rows: impl Fn(usize) -> usize) -> usize {
+        let so = self.scrolloff(scrolloff);
+        let bottom = self.bottom(line_count, rows);
+        let low = if self.top == 0 { 0 } else { self.top + so };
+        let high = if bottom + 1 >= line_count { bottom } else { bottom.saturating_sub(so) };
+        if low > high {
+            return line.clamp(self.top, bottom);
+        }
+        line.clamp(low, high)
+    }
+
+    /// Puts `line` at the top of the screen (`zt`).
+    pub fn line_to_top(&mut self, line: usize, scrolloff: usize) {
+        self.top = line.saturating_sub(self.scrolloff(scrolloff));
+    }
+
+    /// Puts `line` in the middle of the screen (`zz`).
+    pub fn line_to_middle(&mut self, line: usize, rows: impl Fn(usize) -> usize) {
+        let above = self.height.saturating_sub(rows(line)) / 2;
+        self.top = line;
+        let mut used = 0;
+        while self.top > 0 && used + rows(self.top - 1) <= above {
+            self.top -= 1;
+            used += rows(self.top);
+        }
+    }
+
+    /// Puts `line` at the bottom of the screen (`zb`).
+    pub fn line_to_bottom(&mut self, line: usize, line_count: usize, scrolloff: usize, rows: impl Fn(usize) -> usize) {
+        let last = (line + self.scrolloff(scrolloff)).min(line_count.saturating_sub(1));
+        self.top = last;
+        let mut used = rows(last);
+        while self.top > 0 && used + rows(self.top - 1) <= self.height {
+            self.top -= 1;
+            used += rows(self.top);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(text: &str, width: usize, linebreak: bool, showbreak: usize) -> Vec<String> {
+        let line: Vec<char> = text.chars().collect();
+        wrap_line(&line, width, linebreak, showbreak).iter().map(|s| line[s.start..s.end].iter().collect()).collect()
+    }
+
+    fn view(top: usize, height: usize) -> Viewport {
+        Viewport { top, height, width: 80, left: 0 }
+    }
+
+    #[test]
+    fn wraps_lines() {
+        assert_eq!(rows("abcdefghij", 4, false, 0), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(rows("abcd", 4, false, 0), vec!["abcd"]);
+        assert_eq!(rows("", 4, false, 0), vec![""]);
+        // `linebreak` ends rows after a space or other break character,
+        // unless the word fills the whole row.
+        assert_eq!(rows("int a = b+c;", 7, true, 0), vec!["int a ", "= b+c;"]);
+        assert_eq!(rows("one two three", 8, false, 0), vec!["one two ", "three"]);
+        assert_eq!(rows("one two three", 6, true, 0), vec!["one ", "two ", "three"]);
+        assert_eq!(rows("abcdefgh ij", 4, true, 0), vec!["abcd", "efgh", " ij"]);
+        // Rows after the first are `showbreak` narrower.
+        assert_eq!(rows("abcdefghij", 5, false, 2), vec!["abcde", "fgh", "ij"]);
+        // Even when that leaves less than nothing, each row gets a column.
+        assert_eq!(rows("abcd", 2, false, 3), vec!["ab", "c", "d"]);
+    }
+
+    #[test]
+    fn follows_the_cursor_with_scrolloff() {
+        let one = |_| 1;
+        let mut viewport = view(0, 10);
+        viewport.follow_line(9, 100, 3, one);
+        assert_eq!(viewport.top, 3);
+        viewport.follow_line(5, 100, 3, one);
+        assert_eq!(viewport.top, 2);
+        // Near the ends of the file there is nothing more to show.
+        viewport.follow_line(0, 100, 3, one);
+        assert_eq!(viewport.top, 0);
+        viewport.follow_line(99, 100, 3, one);
+        assert_eq!(viewport.top, 90);
+        // Wrapped lines take more rows.
+        let mut viewport = view(0, 10);
+        viewport.follow_line(4, 100, 0, |line| if line == 4 { 8 } else { 1 });
+        assert_eq!(viewport.top, 2);
+    }
+
+    #[test]
+    fn clamps_lines_into_view() {
+        let one = |_| 1;
+        // scrolloff keeps the cursor off the edges of the screen...
+        assert_eq!(view(10, 10).clamp_line(5, 100, 3, one), 13);
+        assert_eq!(view(10, 10).clamp_line(50, 100, 3, one), 16);
+        // ...but not off the first and last lines of the file.
+        assert_eq!(view(0, 10).clamp_line(0, 100, 3, one), 0);
+        assert_eq!(view(90, 10).clamp_line(99, 100, 3, one), 99);
+        assert_eq!(view(95, 10).clamp_line(0, 100, 3, one), 98);
+        // A scrolloff wider than the screen is cut to half of it.
+        assert_eq!(view(10, 4).clamp_line(0, 100, 10, one), 11);
+    }
+
+    #[test]
+    fn scrolls() {
+        let mut viewport = view(5, 10);
+        viewport.scroll(-10, 100);
+        assert_eq!(viewport.top, 0);
+        viewport.scroll(200, 100);
+        assert_eq!(viewport.top, 99);
+        viewport.line_to_top(50, 3);
+        assert_eq!(viewport.top, 47);
+        viewport.line_to_middle(50, |_| 1);
+        assert_eq!(viewport.top, 46);
+        viewport.line_to_bottom(50, 100, 0, |_| 1);
+        assert_eq!(viewport.top, 41);
+        viewport.line_to_bottom(2, 100, 0, |_| 1);
+        assert_eq!(viewport.top, 0);
+
+        let mut viewport = Viewport { width: 20, ..view(0, 10) };
+        viewport.follow_col(30, 5);
+        assert_eq!(viewport.left, 16);
+        viewport.follow_col(18, 5);
+        assert_eq!(viewport.left, 13);
+        viewport.follow_col(2, 5);
+        assert_eq!(viewport.left, 0);
+    }
+}
@@ -1,37 +1,34 @@
 mod editor;
 mod utils;
 
+use editor::config::Config;
 use editor::text_screen::TextScreen;
 use cursive::Cursive;
 use cursive::CursiveExt;
-use cursive::theme::{Theme, BorderStyle, PaletteColor, Color, BaseColor};
 use std::env;
 
 fn main() {
     let mut siv = Cursive::default();
-    
-    let mut theme = Theme::default();
-    theme.shadow = false;
-    theme.borders = BorderStyle::Simple;
-    theme.palette[PaletteColor::Background] = Color::Dark(BaseColor::Black);
-    theme.palette[PaletteColor::View] = Color::Dark(BaseColor::Black);
-    theme.palette[PaletteColor::Primary] = Color::Light(BaseColor::White);
-    theme.palette[PaletteColor::Secondary] = Color::Light(BaseColor::Blue);
-    theme.palette[PaletteColor::Tertiary] = Color::Light(BaseColor::Green);
-    theme.palette[PaletteColor::TitlePrimary] = Color::Light(BaseColor::Green);
-    theme.palette[PaletteColor::TitleSecondary] = Color::Light(BaseColor::Blue);
-    
-    siv.set_theme(theme);
+
+    let (config, config_error) = match Config::load() {
+        Ok(config) => (config, None),
+        Err(e) => (Config::default(), Some(format!("config: {}", e))),
+    };
+    siv.set_theme(config.theme());
+    // Periodic refresh events drive mapping timeouts.
+    siv.set_fps(10);
 
     let args: Vec<String> = env::args().collect();
-    let mut text_screen = TextScreen::default();
+    let mut text_screen = TextScreen::new(config);
+    if config_error.is_some() {
+        text_screen.message = config_error;
+    }
 
     if args.len() > 1 {
         let filename = &args[1];
         text_screen.load_file(filename);
     }
 
-    siv.add_layer(text_screen);
+    siv.add_fullscreen_layer(text_screen);
     siv.run();
 }
-
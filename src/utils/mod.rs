@@ -1 +1,3 @@
 pub mod vec2;
+pub mod text;
+pub mod clipboard;
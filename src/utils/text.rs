@@ -0,0 +1,28 @@
+/// Replaces tab characters with spaces up to the next multiple of `tabstop`.
+pub fn expand_tabs(line: &str, tabstop: usize) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut col = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let width = tabstop - col % tabstop;
+            out.push_str(&" ".repeat(width));
+            col += width;
+        } else {
+            out.push(c);
+            col += 1;
+        }
+    }
+    out
+}
+
+/// Screen column of byte offset `x` in `line` once tabs are expanded.
+pub fn display_col(line: &str, x: usize, tabstop: usize) -> usize {
+    let end = x.min(line.len());
+    line[..end].chars().fold(0, |col, c| {
+        if c == '\t' {
+            col + tabstop - col % tabstop
+        } else {
+            col + 1
+        }
+    })
+}